@@ -5,6 +5,7 @@ trait Colorable {
     fn green(&self) -> String;
     fn blue(&self) -> String;
     fn cyan(&self) -> String;
+    fn yellow(&self) -> String;
     fn bold(&self) -> String;
 }
 
@@ -25,6 +26,10 @@ impl Colorable for String {
         format!("{}{}{}", "\x1b[36m", self, "\x1b[0m")
     }
 
+    fn yellow(&self) -> String {
+        format!("{}{}{}", "\x1b[33m", self, "\x1b[0m")
+    }
+
     fn bold(&self) -> String {
         format!("{}{}{}", "\x1b[1m", self, "\x1b[0m")
     }
@@ -47,6 +52,10 @@ impl Colorable for &str {
         format!("{}{}{}", "\x1b[36m", self, "\x1b[0m")
     }
 
+    fn yellow(&self) -> String {
+        format!("{}{}{}", "\x1b[33m", self, "\x1b[0m")
+    }
+
     fn bold(&self) -> String {
         format!("{}{}{}", "\x1b[1m", self, "\x1b[0m")
     }
@@ -58,6 +67,7 @@ pub enum ErrorLocation {
     Lexer,
     Parser,
     Interpreter,
+    Compiler,
 }
 
 #[derive(Debug)]
@@ -71,6 +81,8 @@ pub struct Error {
     pub message: String,
     pub context: String,
     pub location: ErrorLocation,
+    // Warnings are reported the same way, but don't stop the program.
+    pub warning: bool,
 }
 
 impl Error {
@@ -93,8 +105,14 @@ impl Error {
             message,
             context,
             location,
+            warning: false,
         }
     }
+
+    pub fn into_warning(mut self) -> Self {
+        self.warning = true;
+        self
+    }
 }
 
 impl fmt::Display for Error {
@@ -103,6 +121,7 @@ impl fmt::Display for Error {
             ErrorLocation::Lexer => "Lexing",
             ErrorLocation::Parser => "Parsing",
             ErrorLocation::Interpreter => "Runtime",
+            ErrorLocation::Compiler => "Compile",
         };
 
         let mut col = self.column;
@@ -116,16 +135,21 @@ impl fmt::Display for Error {
         let padding = " ".repeat(col - 1);
         let bar = "^".repeat(self.width).blue().bold();
 
+        let (kind, message) = match self.warning {
+            true => ("warning", self.message.yellow().bold()),
+            false => ("error", self.message.red().bold()),
+        };
+
         write!(
             f,
-            "{stage} error:\n{sidebar_padding}{arrow} {} {}:{} ({})\n{sidebar_padding} {sidebar}\n{} {sidebar} {}\n{sidebar_padding} {sidebar} {padding}{bar} {}",
+            "{stage} {kind}:\n{sidebar_padding}{arrow} {} {}:{} ({})\n{sidebar_padding} {sidebar}\n{} {sidebar} {}\n{sidebar_padding} {sidebar} {padding}{bar} {}",
             self.file.cyan(),
             self.line,
             self.column,
             self.index,
             self.line.to_string().blue().bold(),
             self.context.green(),
-            self.message.red().bold(),
+            message,
         )
     }
 }
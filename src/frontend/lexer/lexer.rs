@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
 use super::{Token, TokenType};
 use crate::errors::Error;
 
@@ -10,6 +15,9 @@ pub struct Lexer {
     pub line: u32,
     pub column: u32,
     pub lines: Vec<String>,
+    pub lib_paths: Vec<PathBuf>,
+    pub imported: HashMap<PathBuf, Option<String>>,
+    pub chain: Vec<PathBuf>,
 }
 
 impl Lexer {
@@ -23,6 +31,9 @@ impl Lexer {
             line: 1,
             column: 1,
             lines: lines,
+            lib_paths: Vec::new(),
+            imported: HashMap::new(),
+            chain: Vec::new(),
         }
     }
 
@@ -53,52 +64,93 @@ impl Lexer {
         let mut combined_tokens = Vec::<Token>::new();
 
         for run in runs {
-            if run.len() != 3 {
-                combined_tokens.append(&mut run.clone());
-                continue;
-            }
-
-            if !run[0].clone().is_identifier() {
-                combined_tokens.append(&mut run.clone());
-                continue;
-            }
+            if !Lexer::is_import(&run) {
+                for token in &run {
+                    self.check_private(token)?;
+                }
 
-            if !run[1].is_string() {
                 combined_tokens.append(&mut run.clone());
                 continue;
             }
 
-            let ident_name = match run[0].clone().token_type {
-                TokenType::Identifier(s) => s,
+            let filename = match run[1].clone().token_type {
+                TokenType::String(s) => s,
                 _ => unreachable!(),
             };
 
-            if ident_name != "import" {
-                combined_tokens.append(&mut run.clone());
+            let alias = match run.len() {
+                5 => match run[3].clone().token_type {
+                    TokenType::Identifier(alias) => Some(alias),
+                    _ => unreachable!(),
+                },
+                _ => None,
+            };
+
+            let path = self.resolve_import(&run[1], &filename)?;
+            let canonical = std::fs::canonicalize(&path).unwrap_or(path.clone());
+
+            let mut chain = self.chain.clone();
+            chain.push(
+                std::fs::canonicalize(&self.filename).unwrap_or(self.filename.clone().into()),
+            );
+
+            if chain.contains(&canonical) {
+                let cycle = chain
+                    .iter()
+                    .skip_while(|file| **file != canonical)
+                    .chain(std::iter::once(&canonical))
+                    .map(|file| file.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(" -> ");
+
+                return Err(self.token_error(&run[1], format!("Import cycle detected: {}", cycle)));
+            }
+
+            // Each file is only included once; later imports of it are no-ops.
+            if let Some(previous) = self.imported.get(&canonical) {
+                if *previous != alias {
+                    return Err(self.token_error(
+                        &run[1],
+                        format!(
+                            "File '{}' was already imported {}",
+                            path.display(),
+                            match previous {
+                                Some(previous) => format!("as '{}'", previous),
+                                None => "without a name".to_string(),
+                            }
+                        ),
+                    ));
+                }
+
                 continue;
             }
 
-            let filename = match run[1].clone().token_type {
-                TokenType::String(s) => s,
-                _ => unreachable!(),
-            };
+            self.imported.insert(canonical, alias.clone());
 
-            let data = std::fs::read_to_string(filename.clone());
+            let data = std::fs::read_to_string(&path);
             if data.is_err() {
-                return Err(self.error(format!(
-                    "Failed to import file '{}': {}",
-                    filename,
-                    data.err().unwrap()
-                )));
+                return Err(self.token_error(
+                    &run[1],
+                    format!(
+                        "Failed to import file '{}': {}",
+                        path.display(),
+                        data.err().unwrap()
+                    ),
+                ));
             }
 
-            let mut lexer = Lexer::new(filename.clone(), data.unwrap());
+            let mut lexer = Lexer::new(path.display().to_string(), data.unwrap() + "\n");
+            lexer.lib_paths = self.lib_paths.clone();
+            lexer.imported = self.imported.clone();
+            lexer.chain = chain;
             let tokens = lexer.tokenise();
+            self.imported = lexer.imported;
 
             match tokens {
-                Ok(tokens) => {
-                    combined_tokens.append(&mut tokens.clone());
-                }
+                Ok(tokens) => match alias {
+                    Some(alias) => combined_tokens.append(&mut Lexer::namespace(&alias, tokens)),
+                    None => combined_tokens.append(&mut tokens.clone()),
+                },
                 Err(e) => {
                     return Err(e);
                 }
@@ -118,6 +170,161 @@ impl Lexer {
         Ok(combined_tokens)
     }
 
+    // `import "file"` or `import "file" as name`
+    fn is_import(run: &[Token]) -> bool {
+        if run.len() != 3 && run.len() != 5 {
+            return false;
+        }
+
+        if run[0].token_type != TokenType::Identifier("import".to_string()) {
+            return false;
+        }
+
+        if !run[1].is_string() {
+            return false;
+        }
+
+        if run.len() == 3 {
+            return true;
+        }
+
+        if run[2].token_type != TokenType::Identifier("as".to_string()) {
+            return false;
+        }
+
+        match &run[3].token_type {
+            TokenType::Identifier(alias) => !alias.contains('.'),
+            _ => false,
+        }
+    }
+
+    // Imports are looked up relative to the importing file first, then in each
+    // of the library search paths in order.
+    fn resolve_import(&self, token: &Token, filename: &str) -> Result<PathBuf, Error> {
+        let path = Path::new(filename);
+
+        let mut candidates = Vec::<PathBuf>::new();
+
+        if path.is_absolute() {
+            candidates.push(path.to_path_buf());
+        } else {
+            let base = Path::new(&self.filename).parent().unwrap_or(Path::new(""));
+            candidates.push(base.join(path));
+
+            for lib_path in &self.lib_paths {
+                candidates.push(lib_path.join(path));
+            }
+        }
+
+        for candidate in &candidates {
+            if candidate.is_file() {
+                return Ok(candidate.clone());
+            }
+        }
+
+        let tried = candidates
+            .iter()
+            .map(|candidate| format!("'{}'", candidate.display()))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        Err(self.token_error(
+            token,
+            format!("Failed to import file '{}', tried: {}", filename, tried),
+        ))
+    }
+
+    // Prefixes every unqualified label and variable name in an imported module
+    // with its alias. Module and function names in call targets are left alone.
+    fn namespace(alias: &str, tokens: Vec<Token>) -> Vec<Token> {
+        let mut namespaced = Vec::<Token>::new();
+
+        for (i, mut token) in tokens.into_iter().enumerate() {
+            let previous = match i {
+                0 => None,
+                _ => Some(namespaced[i - 1].token_type.clone()),
+            };
+
+            let is_call_target = matches!(
+                previous,
+                Some(TokenType::At) | Some(TokenType::Colon) | Some(TokenType::Fn)
+            );
+
+            // `_` discards a value in an unpacking assignment, so it isn't a name.
+            if let TokenType::Identifier(name) = &token.token_type {
+                if !is_call_target && !name.contains('.') && name != "_" {
+                    token.token_type = TokenType::Identifier(format!("{}.{}", alias, name));
+                }
+            }
+
+            if let TokenType::String(name) = &token.token_type {
+                if Lexer::is_loop_var(&namespaced) && !name.contains('.') {
+                    token.token_type = TokenType::String(format!("{}.{}", alias, name));
+                }
+            }
+
+            namespaced.push(token);
+        }
+
+        namespaced
+    }
+
+    // `@iter` loops name their variable with a string, which is the first argument after the
+    // call target, e.g. `@iter:range "i" 0 10`.
+    fn is_loop_var(previous: &[Token]) -> bool {
+        let call = match previous.len() {
+            len if len >= 4 => &previous[len - 4..],
+            _ => return false,
+        };
+
+        if call[0].token_type != TokenType::At
+            || call[1].token_type != TokenType::Identifier("iter".to_string())
+            || call[2].token_type != TokenType::Colon
+        {
+            return false;
+        }
+
+        match &call[3].token_type {
+            TokenType::Identifier(function) => {
+                matches!(function.as_str(), "range" | "iterate" | "each" | "end")
+            }
+            _ => false,
+        }
+    }
+
+    // Names starting with an underscore are private to the module that defines them.
+    fn check_private(&self, token: &Token) -> Result<(), Error> {
+        let name = match &token.token_type {
+            TokenType::Identifier(name) => name,
+            _ => return Ok(()),
+        };
+
+        if name.split('.').skip(1).any(|part| part.starts_with('_')) {
+            return Err(self.token_error(
+                token,
+                format!(
+                    "Cannot access private name from outside its module: {}",
+                    name
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn token_error(&self, token: &Token, message: String) -> Error {
+        Error::new(
+            token.line,
+            token.column,
+            token.index,
+            token.width,
+            token.file.clone(),
+            message,
+            token.context.clone(),
+            crate::errors::ErrorLocation::Lexer,
+        )
+    }
+
     fn error(&self, message: String) -> Error {
         Error::new(
             self.line as usize,
@@ -197,45 +404,106 @@ impl Lexer {
 
         match c.unwrap() {
             ' ' | '\t' | '\r' | '\n' | '=' | '+' | '-' | '*' | '/' | '%' | '!' | '>' | '<'
-            | ':' | '~' | '@' => true,
+            | '&' | '|' | '^' | ':' | '~' | '@' | '{' | '}' | '[' | ']' | ',' | '(' | ')' | '.' => {
+                true
+            }
             _ => false,
         }
     }
 
-    fn get_number(&mut self) -> TokenisationResult {
-        let mut number = String::new();
-        let mut is_float = false;
+    // Reads digits in the given radix, allowing single `_` separators between them.
+    fn get_digits(&mut self, radix: u32) -> String {
+        let mut digits = String::new();
 
         loop {
-            let c = self.peek(0);
-            if c.is_none() {
-                break;
+            match self.peek(0) {
+                Some(c) if c.is_digit(radix) => digits.push(c),
+                Some('_')
+                    if !digits.is_empty() && self.peek(1).is_some_and(|c| c.is_digit(radix)) => {}
+                _ => break,
             }
-            match c.unwrap() {
-                '0'..='9' => {
-                    number.push(c.unwrap());
-                    self.advance();
-                }
-                '.' => {
-                    let next = self.peek(1);
-                    if next.is_some() {
-                        if next.unwrap() == '.' {
-                            break;
-                        }
-                    }
 
-                    if is_float {
-                        return Err(self.error(
-                            "Illegal second decimal point in float literal: '.'".to_string(),
-                        ));
-                    }
-                    number.push(c.unwrap());
-                    self.advance();
-                    is_float = true;
+            self.advance();
+        }
+
+        digits
+    }
+
+    fn get_number(&mut self) -> TokenisationResult {
+        let start = self.index;
+
+        let radix = match (self.peek(0), self.peek(1)) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10,
+        };
+
+        if radix != 10 {
+            self.advance();
+            self.advance();
+
+            let digits = self.get_digits(radix);
+
+            if digits.is_empty() {
+                let prefix = self.source.chars().nth(start as usize + 1).unwrap();
+                return Err(self.error(format!("Expected digits after 0{}", prefix)));
+            }
+
+            if !self.is_boundary() {
+                let c = self.peek(0).unwrap().to_string();
+                return Err(
+                    self.error("Unexpected character in numeric literal: ".to_string() + &c)
+                );
+            }
+
+            let int = match i64::from_str_radix(&digits, radix) {
+                Ok(int) => int,
+                Err(_) => {
+                    let literal: String = self
+                        .source
+                        .chars()
+                        .skip(start as usize)
+                        .take((self.index - start) as usize)
+                        .collect();
+                    return Err(self.error(format!("Integer literal too large: {}", literal)));
                 }
-                _ => {
-                    break;
+            };
+
+            return Ok(self.make_token(TokenType::Int(int), self.index - start));
+        }
+
+        let mut number = self.get_digits(10);
+        let mut is_float = false;
+
+        // A `..` after a number is a range, not a decimal point.
+        if self.peek(0) == Some('.') && self.peek(1) != Some('.') {
+            self.advance();
+            number.push('.');
+            number += &self.get_digits(10);
+            is_float = true;
+
+            if self.peek(0) == Some('.') && self.peek(1) != Some('.') {
+                return Err(
+                    self.error("Illegal second decimal point in float literal: '.'".to_string())
+                );
+            }
+        }
+
+        if let Some('e' | 'E') = self.peek(0) {
+            let sign = matches!(self.peek(1), Some('+' | '-'));
+            let digit = self.peek(if sign { 2 } else { 1 });
+
+            if digit.is_some_and(|c| c.is_ascii_digit()) {
+                number.push('e');
+                self.advance();
+
+                if sign {
+                    number.push(self.advance().unwrap());
                 }
+
+                number += &self.get_digits(10);
+                is_float = true;
             }
         }
 
@@ -244,71 +512,154 @@ impl Lexer {
             return Err(self.error("Unexpected character in numeric literal: ".to_string() + &c));
         }
 
+        let width = self.index - start;
+
         if is_float {
-            Ok(self.make_token(
-                TokenType::Float(number.parse().unwrap()),
-                number.len().try_into().unwrap(),
-            ))
+            Ok(self.make_token(TokenType::Float(number.parse().unwrap()), width))
         } else {
-            Ok(self.make_token(
-                TokenType::Int(number.parse().unwrap()),
-                number.len().try_into().unwrap(),
-            ))
+            // Larger integers can still be made at runtime, e.g. with `@convert:atoi`.
+            let int = match number.parse() {
+                Ok(int) => int,
+                Err(_) => {
+                    return Err(self.error(format!("Integer literal too large: {}", number)));
+                }
+            };
+
+            Ok(self.make_token(TokenType::Int(int), width))
         }
     }
 
-    fn get_string(&mut self) -> TokenisationResult {
-        let mut value = String::new();
+    // Reads the escape sequence after a `\` in a string.
+    fn get_escape(&mut self) -> Result<char, Error> {
+        let c = match self.advance() {
+            Some(c) => c,
+            None => return Err(self.error("Unterminated string literal".to_string())),
+        };
+
+        let code = match c {
+            'n' => return Ok('\n'),
+            'r' => return Ok('\r'),
+            't' => return Ok('\t'),
+            '0' => return Ok('\0'),
+            '\'' => return Ok('\''),
+            '"' => return Ok('"'),
+            '\\' => return Ok('\\'),
+            // \xHH, for a character up to 0xff
+            'x' => {
+                let mut digits = String::new();
+
+                for _ in 0..2 {
+                    match self.peek(0) {
+                        Some(c) if c.is_ascii_hexdigit() => digits.push(c),
+                        _ => {
+                            return Err(self.error("Expected two hex digits after \\x".to_string()))
+                        }
+                    }
 
-        self.advance();
+                    self.advance();
+                }
 
-        let mut escape = false;
+                u32::from_str_radix(&digits, 16).unwrap()
+            }
+            // \u{H...}, for any unicode character
+            'u' => {
+                if self.advance() != Some('{') {
+                    return Err(self.error("Expected '{' after \\u".to_string()));
+                }
 
-        loop {
-            let c = self.advance();
+                let mut digits = String::new();
 
-            if c.is_none() {
-                return Err(self.error("Unterminated string literal".to_string()));
-            }
-
-            let c = c.unwrap();
-
-            if escape {
-                match c {
-                    'n' => value.push('\n'),
-                    'r' => value.push('\r'),
-                    't' => value.push('\t'),
-                    '0' => value.push('\0'),
-                    '\'' => value.push('\''),
-                    '"' => value.push('"'),
-                    '\\' => value.push('\\'),
-                    _ => {
-                        return Err(
-                            self.error("Invalid escape sequence: \\".to_string() + &c.to_string())
-                        )
+                loop {
+                    match self.advance() {
+                        Some('}') if !digits.is_empty() => break,
+                        Some(c) if c.is_ascii_hexdigit() && digits.len() < 6 => digits.push(c),
+                        _ => {
+                            return Err(self.error(
+                                "Expected 1 to 6 hex digits and '}' in \\u{...}".to_string(),
+                            ))
+                        }
                     }
                 }
-                escape = false;
-                continue;
-            }
 
-            if c == '\\' {
-                escape = true;
-                continue;
+                u32::from_str_radix(&digits, 16).unwrap()
             }
+            _ => return Err(self.error("Invalid escape sequence: \\".to_string() + &c.to_string())),
+        };
 
-            if c == '"' {
-                break;
-            }
+        char::from_u32(code)
+            .ok_or_else(|| self.error(format!("Invalid unicode character: {:x}", code)))
+    }
 
-            value.push(c);
+    // Reads a string literal: `"..."`, a triple quoted `"""..."""` that can hold unescaped
+    // quotes, or either of those prefixed with `r` to turn off escapes. Any of them can span
+    // lines, and a newline straight after an opening `"""` is left out.
+    fn get_string(&mut self) -> TokenisationResult {
+        let (line, column, index) = (self.line, self.column, self.index);
+
+        let raw = self.peek(0) == Some('r');
+
+        if raw {
+            self.advance();
         }
 
-        let value_len = value.len();
+        let triple =
+            self.peek(0) == Some('"') && self.peek(1) == Some('"') && self.peek(2) == Some('"');
 
-        Ok(self.make_token(
+        for _ in 0..if triple { 3 } else { 1 } {
+            self.advance();
+        }
+
+        if triple && self.peek(0) == Some('\n') {
+            self.advance();
+            self.line += 1;
+            self.column = 1;
+        }
+
+        let mut value = String::new();
+
+        loop {
+            let c = match self.advance() {
+                Some(c) => c,
+                None => {
+                    return Err(Error::new(
+                        line as usize,
+                        column as usize,
+                        index as usize,
+                        1,
+                        self.filename.clone(),
+                        "Unterminated string literal".to_string(),
+                        self.lines[line as usize - 1].clone(),
+                        crate::errors::ErrorLocation::Lexer,
+                    ))
+                }
+            };
+
+            match c {
+                '"' if !triple => break,
+                '"' if self.peek(0) == Some('"') && self.peek(1) == Some('"') => {
+                    self.advance();
+                    self.advance();
+                    break;
+                }
+                '\\' if !raw => value.push(self.get_escape()?),
+                '\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                    value.push(c);
+                }
+                _ => value.push(c),
+            }
+        }
+
+        // The token starts where the string opened, even if the string spans lines.
+        Ok(Token::new(
             TokenType::String(value),
-            (value_len + 2).try_into().unwrap(),
+            line as usize,
+            column as usize,
+            index as usize,
+            (self.index - index) as usize,
+            self.filename.clone(),
+            self.lines[line as usize - 1].clone(),
         ))
     }
 
@@ -325,6 +676,11 @@ impl Lexer {
                     ident.push(c.unwrap());
                     self.advance();
                 }
+                // Qualified names from namespaced imports, e.g. `dotenv.load`
+                '.' if matches!(self.peek(1), Some('a'..='z' | 'A'..='Z' | '_')) => {
+                    ident.push('.');
+                    self.advance();
+                }
                 _ => {
                     break;
                 }
@@ -343,8 +699,22 @@ impl Lexer {
             "goto" => return Ok(self.make_token(TokenType::Goto, 4)),
             "call" => return Ok(self.make_token(TokenType::Call, 4)),
             "ret" => return Ok(self.make_token(TokenType::Ret, 3)),
+            "fn" => return Ok(self.make_token(TokenType::Fn, 2)),
+            "global" => return Ok(self.make_token(TokenType::Global, 6)),
+            "and" => return Ok(self.make_token(TokenType::And, 3)),
+            "or" => return Ok(self.make_token(TokenType::Or, 2)),
+            "not" => return Ok(self.make_token(TokenType::Not, 3)),
+            "while" => return Ok(self.make_token(TokenType::While, 5)),
+            "for" => return Ok(self.make_token(TokenType::For, 3)),
+            "in" => return Ok(self.make_token(TokenType::In, 2)),
+            "else" => return Ok(self.make_token(TokenType::Else, 4)),
+            "break" => return Ok(self.make_token(TokenType::Break, 5)),
+            "continue" => return Ok(self.make_token(TokenType::Continue, 8)),
+            "const" => return Ok(self.make_token(TokenType::Const, 5)),
+            "match" => return Ok(self.make_token(TokenType::Match, 5)),
             "true" => return Ok(self.make_token(TokenType::Bool(true), 4)),
             "false" => return Ok(self.make_token(TokenType::Bool(false), 5)),
+            "none" => return Ok(self.make_token(TokenType::None, 4)),
             _ => Ok(self.make_token(TokenType::Identifier(ident), ident_size.try_into().unwrap())),
         }
     }
@@ -362,9 +732,19 @@ impl Lexer {
             '=' => Ok(self.make_token(TokenType::Equals, 1)),
             '<' => Ok(self.make_token(TokenType::Less, 1)),
             '>' => Ok(self.make_token(TokenType::Greater, 1)),
+            '&' => Ok(self.make_token(TokenType::Ampersand, 1)),
+            '|' => Ok(self.make_token(TokenType::Pipe, 1)),
+            '^' => Ok(self.make_token(TokenType::Caret, 1)),
             '@' => Ok(self.make_token(TokenType::At, 1)),
             '~' => Ok(self.make_token(TokenType::Tilde, 1)),
             ':' => Ok(self.make_token(TokenType::Colon, 1)),
+            '{' => Ok(self.make_token(TokenType::LBrace, 1)),
+            '}' => Ok(self.make_token(TokenType::RBrace, 1)),
+            '[' => Ok(self.make_token(TokenType::LBracket, 1)),
+            ']' => Ok(self.make_token(TokenType::RBracket, 1)),
+            ',' => Ok(self.make_token(TokenType::Comma, 1)),
+            '(' => Ok(self.make_token(TokenType::LParen, 1)),
+            ')' => Ok(self.make_token(TokenType::RParen, 1)),
             ';' => Ok(self.make_token(TokenType::EOS, 1)),
             '\n' => {
                 self.line += 1;
@@ -392,6 +772,11 @@ impl Lexer {
                 self.advance();
                 Ok(self.make_token(TokenType::EqualsEquals, 2))
             }
+            ('=', '>') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::FatArrow, 2))
+            }
             ('>', '=') => {
                 self.advance();
                 self.advance();
@@ -407,6 +792,36 @@ impl Lexer {
                 self.advance();
                 Ok(self.make_token(TokenType::BangEquals, 2))
             }
+            ('.', '.') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::DotDot, 2))
+            }
+            ('<', '<') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::LessLess, 2))
+            }
+            ('>', '>') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::GreaterGreater, 2))
+            }
+            ('*', '*') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::StarStar, 2))
+            }
+            ('/', '/') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::SlashSlash, 2))
+            }
+            ('%', '%') => {
+                self.advance();
+                self.advance();
+                Ok(self.make_token(TokenType::PercentPercent, 2))
+            }
 
             _ => self.get_single(),
         }
@@ -424,7 +839,15 @@ impl Lexer {
         match c {
             '-' => {
                 let c = self.peek(1);
-                if c.is_some() && c.unwrap().is_ascii_digit() {
+                // A `-` straight after an operand is subtraction, so `a-1` is `a - 1`
+                // while `@foo:bar a -1` still passes a negative literal.
+                let after_operand = self.index > 0
+                    && self
+                        .source
+                        .chars()
+                        .nth(self.index as usize - 1)
+                        .is_some_and(|p| p.is_alphanumeric() || "_\"])".contains(p));
+                if c.is_some() && c.unwrap().is_ascii_digit() && !after_operand {
                     self.advance();
                     let mut token = self.get_number()?;
                     match token.token_type {
@@ -432,16 +855,19 @@ impl Lexer {
                         TokenType::Float(f) => token.token_type = TokenType::Float(-f),
                         _ => {}
                     }
+                    token.index -= 1;
+                    token.column -= 1;
+                    token.width += 1;
                     return Ok(token);
                 }
                 self.advance();
                 Ok(self.make_token(TokenType::Minus, 1))
             }
-            '+' | '*' | '/' | '%' | '!' | '=' | '<' | '>' | '@' | '~' | ':' | '\n' => {
-                self.get_multi()
-            }
+            '+' | '*' | '/' | '%' | '!' | '=' | '<' | '>' | '&' | '|' | '^' | '@' | '~' | ':'
+            | '{' | '}' | '[' | ']' | ',' | '(' | ')' | '.' | '\n' => self.get_multi(),
             '0'..='9' => self.get_number(),
-            'a'..='z' | 'A'..='Z' => self.get_ident(),
+            'r' if self.peek(1) == Some('"') => self.get_string(),
+            'a'..='z' | 'A'..='Z' | '_' => self.get_ident(),
             '#' => {
                 loop {
                     let c = self.advance();
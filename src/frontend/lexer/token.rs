@@ -5,34 +5,65 @@ pub enum TokenType {
     Int(i64),
     Float(f64),
     String(String),
+    None,
 
     // Identifiers
     Identifier(String), // /[a-zA-Z0-9_]+/
 
     // Keywords
-    Goto, // goto
-    If,   // if
-    Call, // call
-    Ret,  // ret
+    Goto,     // goto
+    If,       // if
+    Call,     // call
+    Ret,      // ret
+    Fn,       // fn
+    Global,   // global
+    And,      // and
+    Or,       // or
+    Not,      // not
+    While,    // while
+    For,      // for
+    In,       // in
+    Else,     // else
+    Break,    // break
+    Continue, // continue
+    Const,    // const
+    Match,    // match
 
     // Single-character tokens
-    Colon,   // :
-    Equals,  // =
-    At,      // @
-    Tilde,   // ~
-    Plus,    // +
-    Minus,   // -
-    Star,    // *
-    Slash,   // /
-    Percent, // %
-    Less,    // <
-    Greater, // >
+    Colon,     // :
+    LBrace,    // {
+    RBrace,    // }
+    LBracket,  // [
+    RBracket,  // ]
+    Comma,     // ,
+    LParen,    // (
+    RParen,    // )
+    Equals,    // =
+    At,        // @
+    Tilde,     // ~
+    Plus,      // +
+    Minus,     // -
+    Star,      // *
+    Slash,     // /
+    Percent,   // %
+    Less,      // <
+    Greater,   // >
+    Ampersand, // &
+    Pipe,      // |
+    Caret,     // ^
 
     // Two-character tokens
-    EqualsEquals,  // ==
-    BangEquals,    // !=
-    LessEquals,    // <=
-    GreaterEquals, // >=
+    EqualsEquals,   // ==
+    BangEquals,     // !=
+    LessEquals,     // <=
+    GreaterEquals,  // >=
+    DotDot,         // ..
+    LessLess,       // <<
+    GreaterGreater, // >>
+    StarStar,       // **
+    SlashSlash,     // //
+    PercentPercent, // %%
+    FatArrow,       // =>
 
     // Meta
     EOS,
@@ -86,13 +117,9 @@ impl Token {
         )
     }
 
-    pub fn is_literal(&self) -> bool {
-        match self.token_type {
-            TokenType::Bool(_) | TokenType::Int(_) | TokenType::Float(_) | TokenType::String(_) => {
-                true
-            }
-            _ => false,
-        }
+    // Whether `next` starts right where this token ends, with no whitespace between them.
+    pub fn touches(&self, next: &Token) -> bool {
+        self.line == next.line && self.index + self.width == next.index
     }
 
     pub fn is_identifier(&self) -> bool {
@@ -102,10 +129,6 @@ impl Token {
         }
     }
 
-    pub fn is_value(&self) -> bool {
-        self.is_literal() || self.is_identifier()
-    }
-
     pub fn is_compare(&self) -> bool {
         match self.token_type {
             TokenType::EqualsEquals
@@ -118,17 +141,6 @@ impl Token {
         }
     }
 
-    pub fn is_binop(&self) -> bool {
-        match self.token_type {
-            TokenType::Plus
-            | TokenType::Minus
-            | TokenType::Star
-            | TokenType::Slash
-            | TokenType::Percent => true,
-            _ => false,
-        }
-    }
-
     pub fn is_string(&self) -> bool {
         match self.token_type {
             TokenType::String(_) => true,
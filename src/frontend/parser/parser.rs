@@ -1,18 +1,92 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
 use crate::errors::Error;
 use crate::frontend::lexer::{Token, TokenType};
-
-use super::{BinOp, Compare, Statement, StatementContext, Value};
+use crate::vm::VMValue;
+
+use super::{BinOp, CallTarget, Compare, Expr, Statement, StatementContext, Value};
+
+// A construct opened with `{` that is still waiting for its `}`.
+enum Block {
+    Function(Token),
+    If {
+        token: Token,
+        // Where to jump when the current branch's condition is false, if there's another branch.
+        next: Option<String>,
+        end: String,
+    },
+    Loop {
+        token: Token,
+        kind: &'static str,
+        on_continue: StatementContext,
+        on_break: StatementContext,
+        // Emitted when the loop's `}` is reached.
+        footer: Vec<StatementContext>,
+    },
+}
 
 pub struct Parser {
     pub tokens: Vec<Token>,
     pub current: usize,
+    blocks: Vec<Block>,
+    labels: usize,
+    // Constants declared so far, inlined into every statement parsed after them.
+    constants: HashMap<String, Value>,
+    pub warnings: Vec<Error>,
 }
 
 type ParseResult = Result<Statement, Error>;
+type BlockResult = Result<Vec<Statement>, Error>;
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            blocks: Vec::new(),
+            labels: 0,
+            constants: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // Hidden labels can't clash with user labels, as identifiers can't contain '@'.
+    fn make_label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+
+        format!("@internal:{}:{}", kind, self.labels)
+    }
+
+    fn lower(token: &Token, contexts: Vec<StatementContext>) -> Vec<Statement> {
+        contexts
+            .into_iter()
+            .map(|context| Statement {
+                context,
+                file: token.file.clone(),
+                line: token.line,
+                source: Some(token.context.trim().to_string()),
+            })
+            .collect()
+    }
+
+    fn negate(condition: Expr) -> Expr {
+        Expr::Not(Box::new(condition))
+    }
+
+    // Parses `<expr> {` at the end of a block header, returning the expression.
+    fn parse_block_header(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let expr = Parser::parse_expr(tokens, pos)?;
+
+        if tokens[*pos].token_type != TokenType::LBrace {
+            return Err(tokens[*pos].error(format!(
+                "Expected '{{', found {:?}",
+                tokens[*pos].token_type
+            )));
+        }
+
+        Parser::expect_end(tokens, *pos + 1)?;
+
+        Ok(expr)
     }
 
     fn get_statement(&self) -> Result<Vec<Token>, Error> {
@@ -36,148 +110,384 @@ impl Parser {
         Ok(stmt)
     }
 
-    fn parse_assign_literal(&mut self) -> ParseResult {
-        let tokens = self.get_statement()?;
+    // Parses an expression starting at `tokens[*pos]`, advancing `pos` past it.
+    fn parse_expr(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let mut lhs = Parser::parse_and(tokens, pos)?;
 
-        if tokens[1].token_type != TokenType::Equals {
-            return Err(tokens[1].error(format!("Expected '=', found {:?}", tokens[1].token_type)));
+        while tokens[*pos].token_type == TokenType::Or {
+            *pos += 1;
+
+            let rhs = Parser::parse_and(tokens, pos)?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
         }
 
-        if !tokens[2].is_value() {
-            return Err(tokens[1].error(format!(
-                "Expected literal, found {:?}",
-                tokens[1].token_type
-            )));
+        Ok(lhs)
+    }
+
+    fn parse_and(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let mut lhs = Parser::parse_not(tokens, pos)?;
+
+        while tokens[*pos].token_type == TokenType::And {
+            *pos += 1;
+
+            let rhs = Parser::parse_not(tokens, pos)?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
         }
 
-        self.current += 4;
+        Ok(lhs)
+    }
 
-        let name = match tokens[0].token_type.clone() {
-            TokenType::Identifier(name) => name,
-            _ => unreachable!(),
-        };
+    fn parse_not(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        if tokens[*pos].token_type == TokenType::Not {
+            *pos += 1;
 
-        let value = match tokens[2].token_type.clone() {
-            TokenType::Bool(b) => Value::Bool(b),
-            TokenType::Int(i) => Value::Int(i),
-            TokenType::Float(f) => Value::Float(f),
-            TokenType::String(s) => Value::String(s),
-            TokenType::Identifier(name) => Value::Identifier(name),
+            let expr = Parser::parse_not(tokens, pos)?;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+
+        Parser::parse_compare(tokens, pos)
+    }
+
+    fn parse_compare(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let lhs = Parser::parse_binop(tokens, pos, 5)?;
+
+        if !tokens[*pos].is_compare() {
+            return Ok(lhs);
+        }
+
+        let operator = tokens[*pos].token_type.clone();
+        *pos += 1;
+
+        let rhs = Parser::parse_binop(tokens, pos, 5)?;
+
+        if tokens[*pos].is_compare() {
+            return Err(tokens[*pos].error("Comparisons cannot be chained".to_string()));
+        }
+
+        let compare = match operator {
+            TokenType::EqualsEquals => Compare::Equals(lhs, rhs),
+            TokenType::BangEquals => Compare::NotEquals(lhs, rhs),
+            TokenType::Less => Compare::LessThan(lhs, rhs),
+            TokenType::Greater => Compare::GreaterThan(lhs, rhs),
+            TokenType::LessEquals => Compare::LessThanEquals(lhs, rhs),
+            TokenType::GreaterEquals => Compare::GreaterThanEquals(lhs, rhs),
             _ => unreachable!(),
         };
 
-        Ok(Statement {
-            context: StatementContext::AssignLiteral(name, value),
-            file: tokens[0].file.clone(),
-            line: tokens[0].line,
-        })
+        Ok(Expr::Compare(Box::new(compare)))
     }
 
-    fn parse_assign_binop(&mut self) -> ParseResult {
-        let tokens = self.get_statement()?;
+    // The precedence of a left associative arithmetic or bitwise operator, matching
+    // `BinOp::precedence`.
+    fn binop_precedence(operator: &TokenType) -> Option<u8> {
+        match operator {
+            TokenType::Pipe => Some(5),
+            TokenType::Caret => Some(6),
+            TokenType::Ampersand => Some(7),
+            TokenType::LessLess | TokenType::GreaterGreater => Some(8),
+            TokenType::Plus | TokenType::Minus => Some(9),
+            TokenType::Star
+            | TokenType::Slash
+            | TokenType::Percent
+            | TokenType::SlashSlash
+            | TokenType::PercentPercent => Some(10),
+            _ => None,
+        }
+    }
 
-        if !tokens[2].is_value() {
-            return Err(tokens[1].error(format!(
-                "Expected value, found {:?} (binop:0)",
-                tokens[1].token_type
-            )));
+    // Parses arithmetic and bitwise operators that bind at least as tightly as
+    // `min_precedence`, by precedence climbing.
+    fn parse_binop(tokens: &[Token], pos: &mut usize, min_precedence: u8) -> Result<Expr, Error> {
+        let mut lhs = Parser::parse_unary(tokens, pos)?;
+
+        loop {
+            let operator = tokens[*pos].token_type.clone();
+
+            let precedence = match Parser::binop_precedence(&operator) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => return Ok(lhs),
+            };
+
+            // Like a negative literal, a `-` with space before it but not after starts a new
+            // value, so `@foo:bar a -b` passes two arguments.
+            if operator == TokenType::Minus
+                && !tokens[*pos - 1].touches(&tokens[*pos])
+                && tokens[*pos].touches(&tokens[*pos + 1])
+            {
+                return Ok(lhs);
+            }
+
+            *pos += 1;
+
+            let rhs = Parser::parse_binop(tokens, pos, precedence + 1)?;
+
+            let binop = match operator {
+                TokenType::Plus => BinOp::Add(lhs, rhs),
+                TokenType::Minus => BinOp::Sub(lhs, rhs),
+                TokenType::Star => BinOp::Mul(lhs, rhs),
+                TokenType::Slash => BinOp::Div(lhs, rhs),
+                TokenType::Percent => BinOp::Mod(lhs, rhs),
+                TokenType::SlashSlash => BinOp::EuclidDiv(lhs, rhs),
+                TokenType::PercentPercent => BinOp::EuclidMod(lhs, rhs),
+                TokenType::Ampersand => BinOp::BitAnd(lhs, rhs),
+                TokenType::Pipe => BinOp::BitOr(lhs, rhs),
+                TokenType::Caret => BinOp::BitXor(lhs, rhs),
+                TokenType::LessLess => BinOp::Shl(lhs, rhs),
+                TokenType::GreaterGreater => BinOp::Shr(lhs, rhs),
+                _ => unreachable!(),
+            };
+
+            lhs = Expr::BinOp(Box::new(binop));
         }
+    }
 
-        if !tokens[4].is_value() {
-            return Err(tokens[1].error(format!(
-                "Expected value, found {:?} (binop:1)",
-                tokens[1].token_type
-            )));
+    // Unary minus binds tighter than the other arithmetic operators but looser than `**`, so
+    // `-x ** 2` is `-(x ** 2)`.
+    fn parse_unary(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        if tokens[*pos].token_type == TokenType::Minus {
+            *pos += 1;
+
+            let expr = Parser::parse_unary(tokens, pos)?;
+            return Ok(Expr::Neg(Box::new(expr)));
         }
 
-        self.current += 6;
+        Parser::parse_power(tokens, pos)
+    }
 
-        let name = match tokens[0].token_type.clone() {
-            TokenType::Identifier(name) => name,
-            _ => unreachable!(),
-        };
+    // `**` binds tighter than the other operators and is right associative, so `2 ** 3 ** 2`
+    // is `2 ** (3 ** 2)`.
+    fn parse_power(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let start = *pos;
+        let lhs = Parser::parse_postfix(tokens, pos)?;
 
-        let lhs = match tokens[2].token_type.clone() {
-            TokenType::Bool(b) => Value::Bool(b),
-            TokenType::Int(i) => Value::Int(i),
-            TokenType::Float(f) => Value::Float(f),
-            TokenType::String(s) => Value::String(s),
-            TokenType::Identifier(name) => Value::Identifier(name),
-            _ => unreachable!(),
+        if tokens[*pos].token_type != TokenType::StarStar {
+            return Ok(lhs);
+        }
+
+        *pos += 1;
+
+        let rhs = Parser::parse_unary(tokens, pos)?;
+
+        // The lexer reads `-2` as one negative literal, but `-2 ** 2` is still `-(2 ** 2)`.
+        // `(-2) ** 2` starts with a parenthesis, so it keeps its sign.
+        let (lhs, negate) = match (&tokens[start].token_type, lhs) {
+            (TokenType::Int(i), Expr::Value(Value::Int(_))) if *i < 0 => {
+                (Expr::Value(Value::Int(-i)), true)
+            }
+            (TokenType::Float(f), Expr::Value(Value::Float(_))) if f.is_sign_negative() => {
+                (Expr::Value(Value::Float(-f)), true)
+            }
+            (_, lhs) => (lhs, false),
         };
 
-        let rhs = match tokens[4].token_type.clone() {
+        let pow = Expr::BinOp(Box::new(BinOp::Pow(lhs, rhs)));
+
+        if negate {
+            return Ok(Expr::Neg(Box::new(pow)));
+        }
+
+        Ok(pow)
+    }
+
+    fn parse_postfix(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let mut expr = Parser::parse_atom(tokens, pos)?;
+
+        // An index has to touch the value it indexes, so `@foo:bar xs [1]` stays two arguments.
+        while tokens[*pos].token_type == TokenType::LBracket
+            && tokens[*pos - 1].touches(&tokens[*pos])
+        {
+            let index = Parser::parse_index(tokens, pos)?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+
+        Ok(expr)
+    }
+
+    // Parses `[expr]`, returning the expression inside the brackets.
+    fn parse_index(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        *pos += 1;
+
+        let index = Parser::parse_expr(tokens, pos)?;
+
+        if tokens[*pos].token_type != TokenType::RBracket {
+            return Err(
+                tokens[*pos].error(format!("Expected ']', found {:?}", tokens[*pos].token_type))
+            );
+        }
+
+        *pos += 1;
+
+        Ok(index)
+    }
+
+    fn parse_atom(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let token = &tokens[*pos];
+
+        let value = match token.token_type.clone() {
             TokenType::Bool(b) => Value::Bool(b),
+            TokenType::None => Value::None,
             TokenType::Int(i) => Value::Int(i),
             TokenType::Float(f) => Value::Float(f),
             TokenType::String(s) => Value::String(s),
             TokenType::Identifier(name) => Value::Identifier(name),
-            _ => unreachable!(),
+            TokenType::LBracket => return Parser::parse_array(tokens, pos),
+            TokenType::LParen => return Parser::parse_group(tokens, pos),
+            _ => return Err(token.error(format!("Expected value, found {:?}", token.token_type))),
         };
 
-        let binop = match tokens[3].token_type.clone() {
-            TokenType::Plus => BinOp::Add(lhs, rhs),
-            TokenType::Minus => BinOp::Sub(lhs, rhs),
-            TokenType::Star => BinOp::Mul(lhs, rhs),
-            TokenType::Slash => BinOp::Div(lhs, rhs),
-            TokenType::Percent => BinOp::Mod(lhs, rhs),
-            _ => unreachable!(),
-        };
+        *pos += 1;
 
-        Ok(Statement {
-            context: StatementContext::AssignBinOp(name, binop),
-            file: tokens[0].file.clone(),
-            line: tokens[0].line,
-        })
+        Ok(Expr::Value(value))
     }
 
-    fn parse_assign_call(&mut self) -> ParseResult {
-        let tokens = self.get_statement()?;
+    // Parses a parenthesized group, or a tuple if the parentheses hold a comma: `(a, b)`,
+    // `(a,)` or `()`.
+    fn parse_group(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let open = &tokens[*pos];
+        let mut values = Vec::<Expr>::new();
+        let mut tuple = false;
+
+        *pos += 1;
+
+        loop {
+            match tokens[*pos].token_type {
+                TokenType::RParen if tuple || values.is_empty() => {
+                    tuple = true;
+                    break;
+                }
+                TokenType::EOS => return Err(open.error("Unclosed parenthesis".to_string())),
+                _ => {}
+            }
+
+            values.push(Parser::parse_expr(tokens, pos)?);
+
+            match tokens[*pos].token_type {
+                TokenType::Comma => {
+                    tuple = true;
+                    *pos += 1;
+                }
+                TokenType::RParen => break,
+                TokenType::EOS => return Err(open.error("Unclosed parenthesis".to_string())),
+                _ => {
+                    return Err(tokens[*pos].error(format!(
+                        "Expected ',' or ')', found {:?}",
+                        tokens[*pos].token_type
+                    )))
+                }
+            }
+        }
 
-        if tokens[2].token_type != TokenType::At {
-            return Err(tokens[1].error(format!("Expected '@', found {:?}", tokens[1].token_type)));
+        *pos += 1;
+
+        if tuple {
+            Ok(Expr::Tuple(values))
+        } else {
+            Ok(values.pop().unwrap())
         }
+    }
 
-        if !tokens[3].is_identifier() {
-            return Err(tokens[1].error(format!(
-                "Expected identifier, found {:?}",
-                tokens[1].token_type
-            )));
+    fn parse_array(tokens: &[Token], pos: &mut usize) -> Result<Expr, Error> {
+        let open = &tokens[*pos];
+        let mut values = Vec::<Expr>::new();
+
+        *pos += 1;
+
+        loop {
+            match tokens[*pos].token_type {
+                TokenType::RBracket => break,
+                TokenType::EOS => return Err(open.error("Unterminated array literal".to_string())),
+                _ => {}
+            }
+
+            values.push(Parser::parse_expr(tokens, pos)?);
+
+            match tokens[*pos].token_type {
+                TokenType::Comma => *pos += 1,
+                TokenType::RBracket => break,
+                TokenType::EOS => return Err(open.error("Unterminated array literal".to_string())),
+                _ => {
+                    return Err(tokens[*pos].error(format!(
+                        "Expected ',' or ']', found {:?}",
+                        tokens[*pos].token_type
+                    )))
+                }
+            }
         }
 
-        if tokens[4].token_type != TokenType::Colon {
-            return Err(tokens[1].error(format!("Expected ':', found {:?}", tokens[1].token_type)));
+        *pos += 1;
+
+        Ok(Expr::Array(values))
+    }
+
+    // Parses expressions until the end of the statement. Operators continue an expression
+    // across whitespace, so `@foo:bar a + 1 b` passes two arguments.
+    fn parse_exprs(tokens: &[Token], pos: &mut usize) -> Result<Vec<Expr>, Error> {
+        let mut exprs = Vec::<Expr>::new();
+
+        while tokens[*pos].token_type != TokenType::EOS {
+            exprs.push(Parser::parse_expr(tokens, pos)?);
         }
 
-        if !tokens[5].is_identifier() {
-            return Err(tokens[1].error(format!(
-                "Expected identifier, found {:?}",
-                tokens[1].token_type
+        Ok(exprs)
+    }
+
+    fn expect_end(tokens: &[Token], pos: usize) -> Result<(), Error> {
+        if tokens[pos].token_type != TokenType::EOS {
+            return Err(tokens[pos].error(format!(
+                "Expected end of statement, found {:?}",
+                tokens[pos].token_type
             )));
         }
 
-        let argl = tokens.len() - 7;
+        Ok(())
+    }
 
-        let mut values = Vec::<Value>::new();
+    fn parse_call_target(tokens: &[Token], pos: &mut usize) -> Result<CallTarget, Error> {
+        if tokens[*pos].token_type != TokenType::At {
+            return Err(
+                tokens[*pos].error(format!("Expected '@', found {:?}", tokens[*pos].token_type))
+            );
+        }
 
-        for i in 0..argl {
-            if !tokens[6 + i].is_value() {
-                return Err(tokens[1].error(format!(
-                    "Expected value, found {:?} (call: 0)",
-                    tokens[1].token_type
-                )));
+        let module = match tokens[*pos + 1].token_type.clone() {
+            TokenType::Identifier(name) => name,
+            _ => {
+                return Err(tokens[*pos + 1].error(format!(
+                    "Expected identifier, found {:?}",
+                    tokens[*pos + 1].token_type
+                )))
             }
+        };
 
-            values.push(match tokens[6 + i].token_type.clone() {
-                TokenType::Bool(b) => Value::Bool(b),
-                TokenType::Int(i) => Value::Int(i),
-                TokenType::Float(f) => Value::Float(f),
-                TokenType::String(s) => Value::String(s),
-                TokenType::Identifier(name) => Value::Identifier(name),
-                _ => unreachable!(),
-            });
+        if tokens[*pos + 2].token_type != TokenType::Colon {
+            return Err(tokens[*pos + 2].error(format!(
+                "Expected ':', found {:?}",
+                tokens[*pos + 2].token_type
+            )));
         }
 
+        let function = match tokens[*pos + 3].token_type.clone() {
+            TokenType::Identifier(name) => name,
+            _ => {
+                return Err(tokens[*pos + 3].error(format!(
+                    "Expected identifier, found {:?}",
+                    tokens[*pos + 3].token_type
+                )))
+            }
+        };
+
+        *pos += 4;
+
+        Ok(CallTarget { module, function })
+    }
+
+    fn parse_assign_expr(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        let mut pos = 2;
+        let expr = Parser::parse_expr(&tokens, &mut pos)?;
+        Parser::expect_end(&tokens, pos)?;
+
         self.current += tokens.len();
 
         let name = match tokens[0].token_type.clone() {
@@ -185,52 +495,197 @@ impl Parser {
             _ => unreachable!(),
         };
 
-        let module_name = match tokens[3].token_type.clone() {
+        Ok(Statement {
+            context: StatementContext::AssignExpr(name, expr),
+            file: tokens[0].file.clone(),
+            line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn parse_assign_call(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        let mut pos = 2;
+        let target = Parser::parse_call_target(&tokens, &mut pos)?;
+        self.check_loop_var(&target, &tokens[pos])?;
+        let values = Parser::parse_exprs(&tokens, &mut pos)?;
+
+        self.current += tokens.len();
+
+        let name = match tokens[0].token_type.clone() {
             TokenType::Identifier(name) => name,
             _ => unreachable!(),
         };
 
-        let function_name = match tokens[5].token_type.clone() {
+        Ok(Statement {
+            context: StatementContext::AssignCall(name, target, values),
+            file: tokens[0].file.clone(),
+            line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn parse_assign_index(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        let name = match tokens[0].token_type.clone() {
             TokenType::Identifier(name) => name,
             _ => unreachable!(),
         };
 
+        let mut pos = 1;
+        let mut indices = Vec::<Expr>::new();
+
+        while tokens[pos].token_type == TokenType::LBracket {
+            indices.push(Parser::parse_index(&tokens, &mut pos)?);
+        }
+
+        if tokens[pos].token_type != TokenType::Equals {
+            return Err(
+                tokens[pos].error(format!("Expected '=', found {:?}", tokens[pos].token_type))
+            );
+        }
+
+        pos += 1;
+
+        let value = Parser::parse_expr(&tokens, &mut pos)?;
+        Parser::expect_end(&tokens, pos)?;
+
+        self.current += tokens.len();
+
         Ok(Statement {
-            context: StatementContext::AssignCall(
-                name,
-                crate::frontend::parser::CallTarget {
-                    module: module_name,
-                    function: function_name,
-                },
-                values,
-            ),
+            context: StatementContext::AssignIndex(name, indices, value),
             file: tokens[0].file.clone(),
             line: tokens[0].line,
+            source: None,
         })
     }
 
     fn parse_assign(&mut self) -> ParseResult {
         let tokens = self.get_statement()?;
 
+        if tokens.len() < 4 {
+            return Err(tokens[0].error("Invalid assignment".to_string()));
+        }
+
+        self.check_assignable(&tokens[0])?;
+
+        if tokens[1].token_type == TokenType::LBracket && tokens[0].touches(&tokens[1]) {
+            return self.parse_assign_index();
+        }
+
         if tokens[1].token_type != TokenType::Equals {
             return Err(tokens[1].error(format!("Expected '=', found {:?}", tokens[1].token_type)));
         }
 
-        let binop = tokens[3].is_binop();
+        match tokens[2].token_type {
+            TokenType::Call => self.parse_call_label(),
+            TokenType::At => self.parse_assign_call(),
+            _ => self.parse_assign_expr(),
+        }
+    }
+
+    // `a, b = value`, `a, b = @module:function args...` or `a, b = call label args...`. Calls
+    // are stored into a hidden variable first, as user functions only return when their `ret`
+    // is reached.
+    fn parse_unpack(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
+
+        let mut targets = Vec::<Option<String>>::new();
+        let mut pos = 0;
+
+        loop {
+            let name = match tokens[pos].token_type.clone() {
+                TokenType::Identifier(name) => name,
+                _ => {
+                    return Err(tokens[pos].error(format!(
+                        "Expected identifier, found {:?}",
+                        tokens[pos].token_type
+                    )))
+                }
+            };
+
+            self.check_assignable(&tokens[pos])?;
+
+            if name == "_" {
+                targets.push(None);
+            } else if targets.contains(&Some(name.clone())) {
+                return Err(tokens[pos].error(format!("Duplicate name in assignment: {}", name)));
+            } else {
+                targets.push(Some(name));
+            }
 
-        if tokens.len() == 4 {
-            return self.parse_assign_literal();
-        } else if tokens.len() == 6 && binop {
-            return self.parse_assign_binop();
-        } else if tokens.len() >= 7 && !binop {
-            return self.parse_assign_call();
+            pos += 1;
+
+            match tokens[pos].token_type {
+                TokenType::Comma => pos += 1,
+                TokenType::Equals => break,
+                _ => {
+                    return Err(tokens[pos].error(format!(
+                        "Expected ',' or '=', found {:?}",
+                        tokens[pos].token_type
+                    )))
+                }
+            }
         }
 
-        Err(tokens[1].error("Invalid assignment".to_string()))
+        pos += 1;
+
+        let temp = format!("{}:value", self.make_label("unpack"));
+        let source = Expr::Value(Value::Identifier(temp.clone()));
+
+        let contexts = match tokens[pos].token_type {
+            TokenType::At => {
+                let target = Parser::parse_call_target(&tokens, &mut pos)?;
+                let values = Parser::parse_exprs(&tokens, &mut pos)?;
+
+                vec![
+                    StatementContext::AssignCall(temp, target, values),
+                    StatementContext::Unpack(targets, source),
+                ]
+            }
+            TokenType::Call => {
+                let label = match tokens[pos + 1].token_type.clone() {
+                    TokenType::Identifier(name) => name,
+                    _ => {
+                        return Err(tokens[pos + 1].error(format!(
+                            "Expected identifier, found {:?}",
+                            tokens[pos + 1].token_type
+                        )))
+                    }
+                };
+
+                pos += 2;
+                let values = Parser::parse_exprs(&tokens, &mut pos)?;
+
+                vec![
+                    StatementContext::AssignCallLabel(temp, label, values),
+                    StatementContext::Unpack(targets, source),
+                ]
+            }
+            TokenType::EOS => {
+                return Err(tokens[pos].error("Expected value after '='".to_string()));
+            }
+            _ => {
+                let value = Parser::parse_expr(&tokens, &mut pos)?;
+                Parser::expect_end(&tokens, pos)?;
+
+                vec![StatementContext::Unpack(targets, value)]
+            }
+        };
+
+        self.current += tokens.len();
+
+        Ok(Parser::lower(&tokens[0], contexts))
     }
 
     fn parse_goto_def(&mut self) -> ParseResult {
-        let tokens = self.require(3)?;
+        let tokens = self.get_statement()?;
+
+        if tokens.len() < 3 {
+            return Err(self.tokens[self.current].error("Incomplete statement".to_string()));
+        }
 
         if !tokens[1].is_identifier() {
             return Err(tokens[1].error(format!(
@@ -239,7 +694,29 @@ impl Parser {
             )));
         }
 
-        self.current += 3;
+        let mut params = Vec::<String>::new();
+
+        for token in &tokens[2..tokens.len() - 1] {
+            let param = match token.token_type.clone() {
+                TokenType::Identifier(name) => name,
+                _ => {
+                    return Err(token.error(format!(
+                        "Expected parameter name, found {:?}",
+                        token.token_type
+                    )))
+                }
+            };
+
+            if params.contains(&param) {
+                return Err(token.error(format!("Duplicate parameter name: {}", param)));
+            }
+
+            self.check_assignable(token)?;
+
+            params.push(param);
+        }
+
+        self.current += tokens.len();
 
         let name = match tokens[1].token_type.clone() {
             TokenType::Identifier(name) => name,
@@ -247,9 +724,10 @@ impl Parser {
         };
 
         Ok(Statement {
-            context: StatementContext::GotoDef(name),
+            context: StatementContext::GotoDef(name, params),
             file: tokens[0].file.clone(),
             line: tokens[0].line,
+            source: None,
         })
     }
 
@@ -274,6 +752,7 @@ impl Parser {
             context: StatementContext::Goto(name),
             file: tokens[0].file.clone(),
             line: tokens[0].line,
+            source: None,
         })
     }
 
@@ -291,70 +770,32 @@ impl Parser {
             return Err(tokens[2].error(format!("Expected 'if', found {:?}", tokens[2].token_type)));
         }
 
-        if !tokens[3].is_value() {
-            return Err(
-                tokens[3].error(format!("Expected value, found {:?}", tokens[3].token_type))
-            );
-        }
+        let mut pos = 3;
+        let condition = Parser::parse_expr(&tokens, &mut pos)?;
+        Parser::expect_end(&tokens, pos)?;
 
-        if !tokens[4].is_compare() {
-            return Err(tokens[4].error(format!(
-                "Expected comparison, found {:?}",
-                tokens[4].token_type
-            )));
-        }
-
-        if !tokens[5].is_value() {
-            return Err(
-                tokens[5].error(format!("Expected value, found {:?}", tokens[5].token_type))
-            );
-        }
-
-        self.current += 7;
+        self.current += tokens.len();
 
         let goto_name = match tokens[1].token_type.clone() {
             TokenType::Identifier(name) => name,
             _ => unreachable!(),
         };
 
-        let lhs = match tokens[3].token_type.clone() {
-            TokenType::Bool(b) => Value::Bool(b),
-            TokenType::Int(i) => Value::Int(i),
-            TokenType::Float(f) => Value::Float(f),
-            TokenType::String(s) => Value::String(s),
-            TokenType::Identifier(name) => Value::Identifier(name),
-            _ => unreachable!(),
-        };
-
-        let rhs = match tokens[5].token_type.clone() {
-            TokenType::Bool(b) => Value::Bool(b),
-            TokenType::Int(i) => Value::Int(i),
-            TokenType::Float(f) => Value::Float(f),
-            TokenType::String(s) => Value::String(s),
-            TokenType::Identifier(name) => Value::Identifier(name),
-            _ => unreachable!(),
-        };
-
-        let compare = match tokens[4].token_type.clone() {
-            TokenType::EqualsEquals => Compare::Equals(lhs, rhs),
-            TokenType::BangEquals => Compare::NotEquals(lhs, rhs),
-            TokenType::Less => Compare::LessThan(lhs, rhs),
-            TokenType::Greater => Compare::GreaterThan(lhs, rhs),
-            TokenType::LessEquals => Compare::LessThanEquals(lhs, rhs),
-            TokenType::GreaterEquals => Compare::GreaterThanEquals(lhs, rhs),
-            _ => unreachable!(),
-        };
-
         Ok(Statement {
-            context: StatementContext::GotoIf(goto_name, compare),
+            context: StatementContext::GotoIf(goto_name, condition),
             file: tokens[0].file.clone(),
             line: tokens[0].line,
+            source: None,
         })
     }
 
     fn parse_goto(&mut self) -> ParseResult {
         let tokens = self.get_statement()?;
 
+        if tokens.len() < 3 {
+            return Err(tokens[0].error("Invalid goto statement".to_string()));
+        }
+
         if !tokens[1].is_identifier() {
             return Err(tokens[1].error(format!(
                 "Expected identifier, found {:?}",
@@ -364,7 +805,7 @@ impl Parser {
 
         if tokens.len() == 3 {
             return self.parse_goto_always();
-        } else if tokens.len() == 7 {
+        } else if tokens[2].token_type == TokenType::If {
             return self.parse_goto_if();
         }
 
@@ -374,6 +815,456 @@ impl Parser {
     fn parse_call(&mut self) -> ParseResult {
         let tokens = self.get_statement()?;
 
+        let mut pos = 0;
+        let target = Parser::parse_call_target(&tokens, &mut pos)?;
+        self.check_loop_var(&target, &tokens[pos])?;
+        let values = Parser::parse_exprs(&tokens, &mut pos)?;
+
+        self.current += tokens.len();
+
+        Ok(Statement {
+            context: StatementContext::Call(target, values),
+            file: tokens[0].file.clone(),
+            line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn parse_call_label(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        // Either `call label args...` or `name = call label args...`
+        let start = if tokens[0].token_type == TokenType::Call {
+            0
+        } else {
+            2
+        };
+
+        if tokens.len() < start + 3 {
+            return Err(tokens[start].error("Incomplete statement".to_string()));
+        }
+
+        if !tokens[start + 1].is_identifier() {
+            return Err(tokens[start + 1].error(format!(
+                "Expected identifier, found {:?}",
+                tokens[start + 1].token_type
+            )));
+        }
+
+        let mut pos = start + 2;
+        let values = Parser::parse_exprs(&tokens, &mut pos)?;
+
+        self.current += tokens.len();
+
+        let label = match tokens[start + 1].token_type.clone() {
+            TokenType::Identifier(name) => name,
+            _ => unreachable!(),
+        };
+
+        let context = if start == 0 {
+            StatementContext::CallLabel(label, values)
+        } else {
+            let name = match tokens[0].token_type.clone() {
+                TokenType::Identifier(name) => name,
+                _ => unreachable!(),
+            };
+
+            StatementContext::AssignCallLabel(name, label, values)
+        };
+
+        Ok(Statement {
+            context,
+            file: tokens[0].file.clone(),
+            line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn parse_global(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        if tokens.len() < 3 {
+            return Err(tokens[0].error("Incomplete statement".to_string()));
+        }
+
+        let mut identifiers = Vec::<String>::new();
+
+        for token in &tokens[1..tokens.len() - 1] {
+            match token.token_type.clone() {
+                TokenType::Identifier(name) => {
+                    self.check_assignable(token)?;
+                    identifiers.push(name);
+                }
+                _ => {
+                    return Err(
+                        token.error(format!("Expected identifier, found {:?}", token.token_type))
+                    )
+                }
+            }
+        }
+
+        self.current += tokens.len();
+
+        Ok(Statement {
+            context: StatementContext::Global(identifiers),
+            file: tokens[0].file.clone(),
+            line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn check_assignable(&self, token: &Token) -> Result<(), Error> {
+        match &token.token_type {
+            TokenType::Identifier(name) if self.constants.contains_key(name) => {
+                Err(token.error(format!("Cannot assign to constant: {}", name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // `@iter` loops name their variable with a string, e.g. `@iter:range "i" 0 10`.
+    fn check_loop_var(&self, target: &CallTarget, token: &Token) -> Result<(), Error> {
+        if target.module != "iter"
+            || !matches!(
+                target.function.as_str(),
+                "range" | "iterate" | "each" | "end"
+            )
+        {
+            return Ok(());
+        }
+
+        match &token.token_type {
+            TokenType::String(name) if self.constants.contains_key(name) => {
+                Err(token.error(format!("Cannot assign to constant: {}", name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Evaluates a constant's value. Constants it uses have already been inlined, so any
+    // identifier left over is a variable, which isn't known until the program runs.
+    fn fold(expr: Expr) -> Result<VMValue, String> {
+        match expr {
+            Expr::Value(Value::Identifier(name)) => Err(format!("{} is not a constant", name)),
+            Expr::Value(value) => Ok(VMValue::from(value)),
+            Expr::Array(exprs) => {
+                let mut array = Vec::new();
+
+                for expr in exprs {
+                    array.push(Parser::fold(expr)?);
+                }
+
+                Ok(VMValue::Array(Rc::new(RefCell::new(array))))
+            }
+            Expr::Tuple(exprs) => {
+                let mut tuple = Vec::new();
+
+                for expr in exprs {
+                    tuple.push(Parser::fold(expr)?);
+                }
+
+                Ok(VMValue::Tuple(Rc::new(tuple)))
+            }
+            Expr::Index(value, index) => Parser::fold(*value)?.index(&Parser::fold(*index)?),
+            Expr::BinOp(binop) => match *binop {
+                BinOp::Add(lhs, rhs) => Parser::fold(lhs)?.add(&Parser::fold(rhs)?),
+                BinOp::Sub(lhs, rhs) => Parser::fold(lhs)?.sub(&Parser::fold(rhs)?),
+                BinOp::Mul(lhs, rhs) => Parser::fold(lhs)?.mul(&Parser::fold(rhs)?),
+                BinOp::Div(lhs, rhs) => Parser::fold(lhs)?.div(&Parser::fold(rhs)?),
+                BinOp::Mod(lhs, rhs) => Parser::fold(lhs)?.mod_(&Parser::fold(rhs)?),
+                BinOp::EuclidDiv(lhs, rhs) => Parser::fold(lhs)?.euclid_div(&Parser::fold(rhs)?),
+                BinOp::EuclidMod(lhs, rhs) => Parser::fold(lhs)?.euclid_mod(&Parser::fold(rhs)?),
+                BinOp::Pow(lhs, rhs) => Parser::fold(lhs)?.pow(&Parser::fold(rhs)?),
+                BinOp::BitAnd(lhs, rhs) => Parser::fold(lhs)?.bit_and(&Parser::fold(rhs)?),
+                BinOp::BitOr(lhs, rhs) => Parser::fold(lhs)?.bit_or(&Parser::fold(rhs)?),
+                BinOp::BitXor(lhs, rhs) => Parser::fold(lhs)?.bit_xor(&Parser::fold(rhs)?),
+                BinOp::Shl(lhs, rhs) => Parser::fold(lhs)?.shl(&Parser::fold(rhs)?),
+                BinOp::Shr(lhs, rhs) => Parser::fold(lhs)?.shr(&Parser::fold(rhs)?),
+            },
+            Expr::Compare(compare) => match *compare {
+                Compare::Equals(lhs, rhs) => Parser::fold(lhs)?.equals(&Parser::fold(rhs)?),
+                Compare::NotEquals(lhs, rhs) => Parser::fold(lhs)?.not_equals(&Parser::fold(rhs)?),
+                Compare::LessThan(lhs, rhs) => Parser::fold(lhs)?.less(&Parser::fold(rhs)?),
+                Compare::GreaterThan(lhs, rhs) => Parser::fold(lhs)?.greater(&Parser::fold(rhs)?),
+                Compare::LessThanEquals(lhs, rhs) => {
+                    Parser::fold(lhs)?.less_equals(&Parser::fold(rhs)?)
+                }
+                Compare::GreaterThanEquals(lhs, rhs) => {
+                    Parser::fold(lhs)?.greater_equals(&Parser::fold(rhs)?)
+                }
+            },
+            Expr::And(lhs, rhs) => match Parser::fold_bool(*lhs, "and")? {
+                true => Ok(VMValue::Bool(Parser::fold_bool(*rhs, "and")?)),
+                false => Ok(VMValue::Bool(false)),
+            },
+            Expr::Or(lhs, rhs) => match Parser::fold_bool(*lhs, "or")? {
+                true => Ok(VMValue::Bool(true)),
+                false => Ok(VMValue::Bool(Parser::fold_bool(*rhs, "or")?)),
+            },
+            Expr::Not(expr) => Ok(VMValue::Bool(!Parser::fold_bool(*expr, "not")?)),
+            Expr::Neg(expr) => Parser::fold(*expr)?.neg(),
+        }
+    }
+
+    fn fold_constant(&self, mut expr: Expr) -> Result<VMValue, String> {
+        expr.inline(&self.constants);
+
+        Parser::fold(expr)
+    }
+
+    fn fold_bool(expr: Expr, operator: &str) -> Result<bool, String> {
+        match Parser::fold(expr)? {
+            VMValue::Bool(value) => Ok(value),
+            value => Err(format!("cannot apply {} to {}", operator, value.name())),
+        }
+    }
+
+    // `const NAME = expr` is evaluated here rather than at runtime, and produces no statement.
+    fn parse_const(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
+
+        if !self.blocks.is_empty() {
+            return Err(tokens[0].error("Constants must be declared at the top level".to_string()));
+        }
+
+        if tokens.len() < 5 {
+            return Err(tokens[0].error("Incomplete constant declaration".to_string()));
+        }
+
+        let name = match tokens[1].token_type.clone() {
+            TokenType::Identifier(name) => name,
+            _ => {
+                return Err(tokens[1].error(format!(
+                    "Expected identifier, found {:?}",
+                    tokens[1].token_type
+                )))
+            }
+        };
+
+        if self.constants.contains_key(&name) {
+            return Err(tokens[1].error(format!("Constant already defined: {}", name)));
+        }
+
+        if tokens[2].token_type != TokenType::Equals {
+            return Err(tokens[2].error(format!("Expected '=', found {:?}", tokens[2].token_type)));
+        }
+
+        let mut pos = 3;
+        let mut expr = Parser::parse_expr(&tokens, &mut pos)?;
+        Parser::expect_end(&tokens, pos)?;
+
+        expr.inline(&self.constants);
+
+        let value = Parser::fold(expr)
+            .map_err(|e| tokens[3].error(format!("Cannot evaluate constant {}: {}", name, e)))?;
+
+        let value = match value.to_literal() {
+            Some(value) => value,
+            None => {
+                return Err(tokens[3].error(format!(
+                    "Constant {} must be an int, float, string, bool or none, got {}",
+                    name,
+                    value.name()
+                )))
+            }
+        };
+
+        self.current += tokens.len();
+        self.constants.insert(name, value);
+
+        Ok(Vec::new())
+    }
+
+    // `match value { pattern => label, ..., _ => label }`, with the arms separated by commas or
+    // newlines. Patterns are worked out like constants, so the VM can look them up in a table.
+    fn parse_match(&mut self) -> ParseResult {
+        let token = self.tokens[self.current].clone();
+
+        let close = self.tokens[self.current..]
+            .iter()
+            .position(|t| t.token_type == TokenType::RBrace);
+
+        let tokens = match close {
+            Some(close) => self.tokens[self.current..=self.current + close + 1].to_vec(),
+            None => return Err(token.error("Unclosed match".to_string())),
+        };
+
+        let mut pos = 1;
+        let value = Parser::parse_expr(&tokens, &mut pos)?;
+
+        if tokens[pos].token_type != TokenType::LBrace {
+            return Err(
+                tokens[pos].error(format!("Expected '{{', found {:?}", tokens[pos].token_type))
+            );
+        }
+
+        pos += 1;
+
+        let mut arms = Vec::<(Value, String)>::new();
+        let mut default = None::<String>;
+        // The line of the first arm for each pattern, to point duplicates at it. Patterns are
+        // simple values, so their type and printed form are enough to tell them apart.
+        let mut seen = HashMap::<(String, String), usize>::new();
+        let mut default_line = None::<usize>;
+
+        loop {
+            while matches!(tokens[pos].token_type, TokenType::Comma | TokenType::EOS) {
+                pos += 1;
+            }
+
+            if tokens[pos].token_type == TokenType::RBrace {
+                break;
+            }
+
+            let start = tokens[pos].clone();
+
+            let pattern = if start.token_type == TokenType::Identifier("_".to_string()) {
+                pos += 1;
+                None
+            } else {
+                let expr = Parser::parse_expr(&tokens, &mut pos)?;
+                let pattern = self
+                    .fold_constant(expr)
+                    .map_err(|e| start.error(format!("Cannot evaluate match pattern: {}", e)))?;
+
+                match pattern {
+                    VMValue::Int(_) | VMValue::String(_) | VMValue::Bool(_) => Some(pattern),
+                    _ => {
+                        return Err(start.error(format!(
+                            "Match patterns must be ints, strings or bools, got {}",
+                            pattern.name()
+                        )))
+                    }
+                }
+            };
+
+            if tokens[pos].token_type != TokenType::FatArrow {
+                return Err(
+                    tokens[pos].error(format!("Expected '=>', found {:?}", tokens[pos].token_type))
+                );
+            }
+
+            let label = match tokens[pos + 1].token_type.clone() {
+                TokenType::Identifier(name) => name,
+                _ => {
+                    return Err(tokens[pos + 1].error(format!(
+                        "Expected label, found {:?}",
+                        tokens[pos + 1].token_type
+                    )))
+                }
+            };
+
+            pos += 2;
+
+            if !matches!(
+                tokens[pos].token_type,
+                TokenType::Comma | TokenType::EOS | TokenType::RBrace
+            ) {
+                return Err(tokens[pos].error(format!(
+                    "Expected ',' or '}}', found {:?}",
+                    tokens[pos].token_type
+                )));
+            }
+
+            // Only the first of several equal arms can ever be taken.
+            match pattern {
+                Some(pattern) => match seen.get(&(pattern.name(), pattern.to_string())) {
+                    Some(line) => self.warnings.push(
+                        start
+                            .error(format!(
+                                "Duplicate match arm for {} (first on line {}), it will never be taken",
+                                pattern.to_literal().unwrap().rewrite(),
+                                line
+                            ))
+                            .into_warning(),
+                    ),
+                    None => {
+                        seen.insert((pattern.name(), pattern.to_string()), start.line);
+                        arms.push((pattern.to_literal().unwrap(), label));
+                    }
+                },
+                None => match default_line {
+                    Some(line) => self.warnings.push(
+                        start
+                            .error(format!(
+                                "Duplicate '_' arm (first on line {}), it will never be taken",
+                                line
+                            ))
+                            .into_warning(),
+                    ),
+                    None => {
+                        default_line = Some(start.line);
+                        default = Some(label);
+                    }
+                },
+            }
+        }
+
+        Parser::expect_end(&tokens, pos + 1)?;
+
+        self.current += tokens.len();
+
+        Ok(Statement {
+            context: StatementContext::Match(value, arms, default),
+            file: token.file.clone(),
+            line: token.line,
+            source: None,
+        })
+    }
+
+    fn parse_ret(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        let value = if tokens.len() > 2 {
+            let mut pos = 1;
+            let mut values = vec![Parser::parse_expr(&tokens, &mut pos)?];
+
+            // `ret a, b` returns several values as a tuple.
+            while tokens[pos].token_type == TokenType::Comma {
+                pos += 1;
+                values.push(Parser::parse_expr(&tokens, &mut pos)?);
+            }
+
+            Parser::expect_end(&tokens, pos)?;
+
+            match values.len() {
+                1 => values.pop(),
+                _ => Some(Expr::Tuple(values)),
+            }
+        } else {
+            None
+        };
+
+        self.current += tokens.len();
+
+        Ok(Statement {
+            context: StatementContext::Ret(value),
+            file: tokens[0].file.clone(),
+            line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn parse_fn_def(&mut self) -> ParseResult {
+        let tokens = self.get_statement()?;
+
+        for block in &self.blocks {
+            if let Block::Function(function) = block {
+                return Err(tokens[0].error(format!(
+                    "Functions cannot be nested (enclosing function defined on line {})",
+                    function.line
+                )));
+            }
+        }
+
+        if !self.blocks.is_empty() {
+            return Err(tokens[0].error("Functions must be defined at the top level".to_string()));
+        }
+
+        if tokens.len() < 6 {
+            return Err(tokens[0].error("Incomplete function definition".to_string()));
+        }
+
         if !tokens[1].is_identifier() {
             return Err(tokens[1].error(format!(
                 "Expected identifier, found {:?}",
@@ -382,37 +1273,49 @@ impl Parser {
         }
 
         if tokens[2].token_type != TokenType::Colon {
-            return Err(tokens[1].error(format!("Expected ':', found {:?}", tokens[1].token_type)));
+            return Err(tokens[2].error(format!("Expected ':', found {:?}", tokens[2].token_type)));
         }
 
         if !tokens[3].is_identifier() {
-            return Err(tokens[1].error(format!(
+            return Err(tokens[3].error(format!(
                 "Expected identifier, found {:?}",
-                tokens[1].token_type
+                tokens[3].token_type
             )));
         }
 
-        let argl = tokens.len() - 5;
-        let mut values = Vec::<Value>::new();
+        let brace = tokens.len() - 2;
 
-        for i in 0..argl {
-            if !tokens[4 + i].is_value() {
-                return Err(
-                    tokens[1].error(format!("Expected value, found {:?}", tokens[1].token_type))
-                );
+        if tokens[brace].token_type != TokenType::LBrace {
+            return Err(tokens[brace].error(format!(
+                "Expected '{{', found {:?}",
+                tokens[brace].token_type
+            )));
+        }
+
+        let mut params = Vec::<String>::new();
+
+        for token in &tokens[4..brace] {
+            let param = match token.token_type.clone() {
+                TokenType::Identifier(name) => name,
+                _ => {
+                    return Err(token.error(format!(
+                        "Expected parameter name, found {:?}",
+                        token.token_type
+                    )))
+                }
+            };
+
+            if params.contains(&param) {
+                return Err(token.error(format!("Duplicate parameter name: {}", param)));
             }
 
-            values.push(match tokens[4 + i].token_type.clone() {
-                TokenType::Bool(b) => Value::Bool(b),
-                TokenType::Int(i) => Value::Int(i),
-                TokenType::Float(f) => Value::Float(f),
-                TokenType::String(s) => Value::String(s),
-                TokenType::Identifier(name) => Value::Identifier(name),
-                _ => unreachable!(),
-            });
+            self.check_assignable(token)?;
+
+            params.push(param);
         }
 
         self.current += tokens.len();
+        self.blocks.push(Block::Function(tokens[0].clone()));
 
         let module_name = match tokens[1].token_type.clone() {
             TokenType::Identifier(name) => name,
@@ -424,65 +1327,341 @@ impl Parser {
             _ => unreachable!(),
         };
 
-        return Ok(Statement {
-            context: StatementContext::Call(
-                crate::frontend::parser::CallTarget {
+        Ok(Statement {
+            context: StatementContext::FuncDef(
+                CallTarget {
                     module: module_name,
                     function: function_name,
                 },
-                values,
+                params,
             ),
             file: tokens[0].file.clone(),
             line: tokens[0].line,
+            source: None,
+        })
+    }
+
+    fn parse_if(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
+
+        let mut pos = 1;
+        let condition = Parser::parse_block_header(&tokens, &mut pos)?;
+
+        self.current += tokens.len();
+
+        let next = self.make_label("if");
+        let end = self.make_label("if");
+
+        self.blocks.push(Block::If {
+            token: tokens[0].clone(),
+            next: Some(next.clone()),
+            end,
         });
+
+        Ok(Parser::lower(
+            &tokens[0],
+            vec![StatementContext::GotoIf(next, Parser::negate(condition))],
+        ))
     }
 
-    fn parse_call_label(&mut self) -> ParseResult {
-        let tokens = self.require(3)?;
+    fn parse_else(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
 
-        if !tokens[1].is_identifier() {
-            return Err(tokens[1].error(format!(
-                "Expected identifier, found {:?}",
-                tokens[1].token_type
-            )));
-        }
+        let (token, next, end) = match self.blocks.pop() {
+            Some(Block::If {
+                token,
+                next: Some(next),
+                end,
+            }) => (token, next, end),
+            Some(Block::If { next: None, .. }) => {
+                return Err(tokens[1].error("Unexpected 'else' after an 'else' branch".to_string()))
+            }
+            _ => return Err(tokens[1].error("Unexpected 'else' outside of an if".to_string())),
+        };
 
-        self.current += 3;
+        // Finishing a branch skips the rest of the chain; a false condition lands on the next one.
+        let mut contexts = vec![
+            StatementContext::Goto(end.clone()),
+            StatementContext::GotoDef(next, Vec::new()),
+        ];
+
+        let next = if tokens[2].token_type == TokenType::If {
+            let mut pos = 3;
+            let condition = Parser::parse_block_header(&tokens, &mut pos)?;
+
+            let next = self.make_label("if");
+            contexts.push(StatementContext::GotoIf(
+                next.clone(),
+                Parser::negate(condition),
+            ));
+
+            Some(next)
+        } else {
+            if tokens[2].token_type != TokenType::LBrace {
+                return Err(tokens[2].error(format!(
+                    "Expected '{{' or 'if', found {:?}",
+                    tokens[2].token_type
+                )));
+            }
+
+            Parser::expect_end(&tokens, 3)?;
+
+            None
+        };
+
+        self.current += tokens.len();
+        self.blocks.push(Block::If { token, next, end });
+
+        Ok(Parser::lower(&tokens[0], contexts))
+    }
+
+    fn parse_while(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
+
+        let mut pos = 1;
+        let condition = Parser::parse_block_header(&tokens, &mut pos)?;
+
+        self.current += tokens.len();
+
+        let start = self.make_label("while");
+        let end = self.make_label("while");
+
+        self.blocks.push(Block::Loop {
+            token: tokens[0].clone(),
+            kind: "while",
+            on_continue: StatementContext::Goto(start.clone()),
+            on_break: StatementContext::Goto(end.clone()),
+            footer: vec![
+                StatementContext::Goto(start.clone()),
+                StatementContext::GotoDef(end.clone(), Vec::new()),
+            ],
+        });
+
+        Ok(Parser::lower(
+            &tokens[0],
+            vec![
+                StatementContext::GotoDef(start, Vec::new()),
+                StatementContext::GotoIf(end, Parser::negate(condition)),
+            ],
+        ))
+    }
+
+    fn parse_for(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
 
         let name = match tokens[1].token_type.clone() {
             TokenType::Identifier(name) => name,
-            _ => unreachable!(),
+            _ => {
+                return Err(tokens[1].error(format!(
+                    "Expected identifier, found {:?}",
+                    tokens[1].token_type
+                )))
+            }
         };
 
-        Ok(Statement {
-            context: StatementContext::CallLabel(name),
-            file: tokens[0].file.clone(),
-            line: tokens[0].line,
-        })
+        self.check_assignable(&tokens[1])?;
+
+        if tokens[2].token_type != TokenType::In {
+            return Err(tokens[2].error(format!("Expected 'in', found {:?}", tokens[2].token_type)));
+        }
+
+        let mut pos = 3;
+        let from = Parser::parse_expr(&tokens, &mut pos)?;
+
+        if tokens[pos].token_type == TokenType::LBrace {
+            Parser::expect_end(&tokens, pos + 1)?;
+            self.current += tokens.len();
+
+            return Ok(self.lower_for_each(&tokens[0], name, from));
+        }
+
+        if tokens[pos].token_type != TokenType::DotDot {
+            return Err(tokens[pos].error(format!(
+                "Expected '..' or '{{', found {:?}",
+                tokens[pos].token_type
+            )));
+        }
+
+        pos += 1;
+        let to = Parser::parse_block_header(&tokens, &mut pos)?;
+
+        self.current += tokens.len();
+
+        let start = self.make_label("for");
+        let next = self.make_label("for");
+        let end = self.make_label("for");
+
+        // The upper bound is evaluated once, into a variable user code can't name.
+        let limit = format!("{}:to", start);
+        let var = Expr::Value(Value::Identifier(name.clone()));
+
+        self.blocks.push(Block::Loop {
+            token: tokens[0].clone(),
+            kind: "for",
+            on_continue: StatementContext::Goto(next.clone()),
+            on_break: StatementContext::Goto(end.clone()),
+            footer: vec![
+                StatementContext::GotoDef(next, Vec::new()),
+                StatementContext::AssignExpr(
+                    name.clone(),
+                    Expr::BinOp(Box::new(BinOp::Add(
+                        var.clone(),
+                        Expr::Value(Value::Int(1)),
+                    ))),
+                ),
+                StatementContext::Goto(start.clone()),
+                StatementContext::GotoDef(end.clone(), Vec::new()),
+            ],
+        });
+
+        Ok(Parser::lower(
+            &tokens[0],
+            vec![
+                StatementContext::AssignExpr(name, from),
+                StatementContext::AssignExpr(limit.clone(), to),
+                StatementContext::GotoDef(start, Vec::new()),
+                StatementContext::GotoIf(
+                    end,
+                    Expr::Compare(Box::new(Compare::GreaterThanEquals(
+                        var,
+                        Expr::Value(Value::Identifier(limit)),
+                    ))),
+                ),
+            ],
+        ))
     }
 
-    fn parse_ret(&mut self) -> ParseResult {
+    // `for x in items` loops over a collection with the iter module, which keeps its own loop
+    // state, so `break` has to go through `@iter:stop` rather than jumping out.
+    fn lower_for_each(&mut self, token: &Token, name: String, items: Expr) -> Vec<Statement> {
+        let next = self.make_label("for");
+        let iter = |function: &str| CallTarget {
+            module: "iter".to_string(),
+            function: function.to_string(),
+        };
+
+        self.blocks.push(Block::Loop {
+            token: token.clone(),
+            kind: "for",
+            on_continue: StatementContext::Goto(next.clone()),
+            on_break: StatementContext::Call(iter("stop"), Vec::new()),
+            footer: vec![
+                StatementContext::GotoDef(next, Vec::new()),
+                StatementContext::Call(iter("end"), Vec::new()),
+            ],
+        });
+
+        Parser::lower(
+            token,
+            vec![StatementContext::Call(
+                iter("each"),
+                vec![Expr::Value(Value::String(name)), items],
+            )],
+        )
+    }
+
+    fn parse_loop_jump(&mut self) -> ParseResult {
         let tokens = self.require(2)?;
 
+        let context = self.blocks.iter().rev().find_map(|block| match block {
+            Block::Loop {
+                on_continue,
+                on_break,
+                ..
+            } => Some(match tokens[0].token_type {
+                TokenType::Break => on_break.clone(),
+                _ => on_continue.clone(),
+            }),
+            _ => None,
+        });
+
+        let context = match context {
+            Some(context) => context,
+            None => {
+                let keyword = match tokens[0].token_type {
+                    TokenType::Break => "break",
+                    _ => "continue",
+                };
+
+                return Err(tokens[0].error(format!("Unexpected '{}' outside of a loop", keyword)));
+            }
+        };
+
         self.current += 2;
 
         Ok(Statement {
-            context: StatementContext::Ret,
+            context,
             file: tokens[0].file.clone(),
             line: tokens[0].line,
+            source: None,
         })
     }
 
-    fn parse_statement(&mut self) -> ParseResult {
+    fn parse_block_end(&mut self) -> BlockResult {
+        let tokens = self.get_statement()?;
+
+        if tokens.len() > 2 && tokens[1].token_type == TokenType::Else {
+            return self.parse_else();
+        }
+
+        Parser::expect_end(&tokens, 1)?;
+
+        // A loop's footer does the work of its header, such as stepping a `for` variable, so
+        // errors in it point at the header.
+        let mut source = tokens[0].clone();
+
+        let contexts = match self.blocks.pop() {
+            Some(Block::Function(_)) => vec![StatementContext::FuncEnd],
+            Some(Block::If { next, end, .. }) => {
+                let mut contexts = Vec::new();
+
+                if let Some(next) = next {
+                    contexts.push(StatementContext::GotoDef(next, Vec::new()));
+                }
+
+                contexts.push(StatementContext::GotoDef(end, Vec::new()));
+                contexts
+            }
+            Some(Block::Loop { token, footer, .. }) => {
+                source = token;
+                footer
+            }
+            None => return Err(tokens[0].error("Unexpected '}' outside of a block".to_string())),
+        };
+
+        self.current += tokens.len();
+
+        Ok(Parser::lower(&source, contexts))
+    }
+
+    fn parse_statement(&mut self) -> BlockResult {
         let token = self.tokens[self.current].clone();
 
         match token.token_type {
+            TokenType::If => return self.parse_if(),
+            TokenType::While => return self.parse_while(),
+            TokenType::For => return self.parse_for(),
+            TokenType::Const => return self.parse_const(),
+            TokenType::Identifier(_)
+                if self.tokens[self.current + 1].token_type == TokenType::Comma =>
+            {
+                return self.parse_unpack()
+            }
+            TokenType::RBrace => return self.parse_block_end(),
+            _ => {}
+        }
+
+        let statement = match token.token_type {
             TokenType::Tilde => self.parse_goto_def(),
             TokenType::At => self.parse_call(),
             TokenType::Goto => self.parse_goto(),
             TokenType::Identifier(_) => self.parse_assign(),
             TokenType::Call => self.parse_call_label(),
             TokenType::Ret => self.parse_ret(),
+            TokenType::Global => self.parse_global(),
+            TokenType::Match => self.parse_match(),
+            TokenType::Fn => self.parse_fn_def(),
+            TokenType::Break | TokenType::Continue => self.parse_loop_jump(),
             TokenType::EOS => {
                 self.current += 1;
 
@@ -490,11 +1669,14 @@ impl Parser {
                     context: StatementContext::EOS,
                     file: token.file.clone(),
                     line: token.line,
+                    source: None,
                 })
             }
 
             _ => Err(token.error(format!("Unexpected token"))),
-        }
+        };
+
+        Ok(vec![statement?])
     }
 
     pub fn parse(&mut self) -> Result<Vec<Statement>, Error> {
@@ -505,13 +1687,30 @@ impl Parser {
                 break;
             }
 
-            let statement = self.parse_statement()?;
+            for mut statement in self.parse_statement()? {
+                if statement.context == StatementContext::EOS {
+                    continue;
+                }
 
-            if statement.context == StatementContext::EOS {
-                continue;
+                for expr in statement.context.exprs_mut() {
+                    expr.inline(&self.constants);
+                }
+
+                statements.push(statement);
             }
+        }
 
-            statements.push(statement);
+        match self.blocks.last() {
+            Some(Block::Function(token)) => {
+                return Err(token.error("Unterminated function definition".to_string()))
+            }
+            Some(Block::If { token, .. }) => {
+                return Err(token.error("Unterminated if block".to_string()))
+            }
+            Some(Block::Loop { token, kind, .. }) => {
+                return Err(token.error(format!("Unterminated {} loop", kind)))
+            }
+            None => {}
         }
 
         Ok(statements)
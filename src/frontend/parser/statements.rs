@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     String(String),
     Int(i64),
     Float(f64),
     Bool(bool),
+    None,
     Identifier(String),
 }
 
@@ -25,63 +28,249 @@ impl Value {
             Value::Int(int) => int.to_string(),
             Value::Float(float) => float.to_string(),
             Value::Bool(bool) => bool.to_string(),
+            Value::None => "none".to_string(),
             Value::Identifier(identifier) => identifier.to_string(),
         }
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Value(Value),
+    Array(Vec<Expr>),
+    Tuple(Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    BinOp(Box<BinOp>),
+    Compare(Box<Compare>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+}
+
+impl Expr {
+    // Higher binds tighter. Used to decide where `rewrite` needs parentheses.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Expr::Or(_, _) => 1,
+            Expr::And(_, _) => 2,
+            Expr::Not(_) => 3,
+            Expr::Compare(_) => 4,
+            Expr::BinOp(binop) => binop.precedence(),
+            Expr::Neg(_) => 11,
+            Expr::Value(_) | Expr::Array(_) | Expr::Tuple(_) | Expr::Index(_, _) => 13,
+        }
+    }
+
+    // Rewrites an operand of an operator with the given precedence. Most operators are left
+    // associative, so an operand of equal precedence on the other side still needs parentheses.
+    fn rewrite_operand(&self, precedence: u8, other_side: bool) -> String {
+        if self.precedence() < precedence || (other_side && self.precedence() == precedence) {
+            format!("({})", self.rewrite())
+        } else {
+            self.rewrite()
+        }
+    }
+
+    pub fn rewrite(&self) -> String {
+        match self {
+            Expr::Value(value) => value.rewrite(),
+            Expr::Array(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|value| value.rewrite())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Expr::Tuple(values) => match values.as_slice() {
+                [value] => format!("({},)", value.rewrite()),
+                _ => format!(
+                    "({})",
+                    values
+                        .iter()
+                        .map(|value| value.rewrite())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            },
+            Expr::Index(value, index) => {
+                format!("{}[{}]", value.rewrite_operand(13, false), index.rewrite())
+            }
+            Expr::BinOp(binop) => binop.rewrite(),
+            Expr::Compare(compare) => compare.rewrite(),
+            Expr::And(left, right) => format!(
+                "{} and {}",
+                left.rewrite_operand(2, false),
+                right.rewrite_operand(2, true)
+            ),
+            Expr::Or(left, right) => format!(
+                "{} or {}",
+                left.rewrite_operand(1, false),
+                right.rewrite_operand(1, true)
+            ),
+            Expr::Not(expr) => format!("not {}", expr.rewrite_operand(3, false)),
+            Expr::Neg(expr) => format!("-{}", expr.rewrite_operand(11, false)),
+        }
+    }
+
+    // Replaces every identifier naming a constant with the constant's value.
+    pub fn inline(&mut self, constants: &HashMap<String, Value>) {
+        match self {
+            Expr::Value(Value::Identifier(name)) => {
+                if let Some(value) = constants.get(name) {
+                    *self = Expr::Value(value.clone());
+                }
+            }
+            Expr::Value(_) => {}
+            Expr::Array(values) | Expr::Tuple(values) => {
+                for value in values {
+                    value.inline(constants);
+                }
+            }
+            Expr::Index(left, right) | Expr::And(left, right) | Expr::Or(left, right) => {
+                left.inline(constants);
+                right.inline(constants);
+            }
+            Expr::BinOp(binop) => {
+                let (left, right) = binop.operands_mut();
+                left.inline(constants);
+                right.inline(constants);
+            }
+            Expr::Compare(compare) => {
+                let (left, right) = compare.operands_mut();
+                left.inline(constants);
+                right.inline(constants);
+            }
+            Expr::Not(expr) | Expr::Neg(expr) => expr.inline(constants),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum BinOp {
-    Add(Value, Value),
-    Sub(Value, Value),
-    Mul(Value, Value),
-    Div(Value, Value),
-    Mod(Value, Value),
+    Add(Expr, Expr),
+    Sub(Expr, Expr),
+    Mul(Expr, Expr),
+    Div(Expr, Expr),
+    Mod(Expr, Expr),
+    EuclidDiv(Expr, Expr),
+    EuclidMod(Expr, Expr),
+    Pow(Expr, Expr),
+    BitAnd(Expr, Expr),
+    BitOr(Expr, Expr),
+    BitXor(Expr, Expr),
+    Shl(Expr, Expr),
+    Shr(Expr, Expr),
 }
 
 impl BinOp {
-    pub fn rewrite(&self) -> String {
+    fn parts(&self) -> (&Expr, &Expr, &'static str) {
+        match self {
+            BinOp::Add(left, right) => (left, right, "+"),
+            BinOp::Sub(left, right) => (left, right, "-"),
+            BinOp::Mul(left, right) => (left, right, "*"),
+            BinOp::Div(left, right) => (left, right, "/"),
+            BinOp::Mod(left, right) => (left, right, "%"),
+            BinOp::EuclidDiv(left, right) => (left, right, "//"),
+            BinOp::EuclidMod(left, right) => (left, right, "%%"),
+            BinOp::Pow(left, right) => (left, right, "**"),
+            BinOp::BitAnd(left, right) => (left, right, "&"),
+            BinOp::BitOr(left, right) => (left, right, "|"),
+            BinOp::BitXor(left, right) => (left, right, "^"),
+            BinOp::Shl(left, right) => (left, right, "<<"),
+            BinOp::Shr(left, right) => (left, right, ">>"),
+        }
+    }
+
+    fn operands_mut(&mut self) -> (&mut Expr, &mut Expr) {
+        match self {
+            BinOp::Add(left, right)
+            | BinOp::Sub(left, right)
+            | BinOp::Mul(left, right)
+            | BinOp::Div(left, right)
+            | BinOp::Mod(left, right)
+            | BinOp::EuclidDiv(left, right)
+            | BinOp::EuclidMod(left, right)
+            | BinOp::Pow(left, right)
+            | BinOp::BitAnd(left, right)
+            | BinOp::BitOr(left, right)
+            | BinOp::BitXor(left, right)
+            | BinOp::Shl(left, right)
+            | BinOp::Shr(left, right) => (left, right),
+        }
+    }
+
+    pub fn precedence(&self) -> u8 {
         match self {
-            BinOp::Add(left, right) => format!("{} + {}", left.rewrite(), right.rewrite()),
-            BinOp::Sub(left, right) => format!("{} - {}", left.rewrite(), right.rewrite()),
-            BinOp::Mul(left, right) => format!("{} * {}", left.rewrite(), right.rewrite()),
-            BinOp::Div(left, right) => format!("{} / {}", left.rewrite(), right.rewrite()),
-            BinOp::Mod(left, right) => format!("{} % {}", left.rewrite(), right.rewrite()),
+            BinOp::BitOr(_, _) => 5,
+            BinOp::BitXor(_, _) => 6,
+            BinOp::BitAnd(_, _) => 7,
+            BinOp::Shl(_, _) | BinOp::Shr(_, _) => 8,
+            BinOp::Add(_, _) | BinOp::Sub(_, _) => 9,
+            BinOp::Mul(_, _)
+            | BinOp::Div(_, _)
+            | BinOp::Mod(_, _)
+            | BinOp::EuclidDiv(_, _)
+            | BinOp::EuclidMod(_, _) => 10,
+            BinOp::Pow(_, _) => 12,
         }
     }
+
+    pub fn rewrite(&self) -> String {
+        let (left, right, operator) = self.parts();
+        let precedence = self.precedence();
+        // `**` is the only right associative operator.
+        let right_associative = matches!(self, BinOp::Pow(_, _));
+
+        format!(
+            "{} {} {}",
+            left.rewrite_operand(precedence, right_associative),
+            operator,
+            right.rewrite_operand(precedence, !right_associative)
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Compare {
-    Equals(Value, Value),
-    NotEquals(Value, Value),
-    LessThan(Value, Value),
-    GreaterThan(Value, Value),
-    LessThanEquals(Value, Value),
-    GreaterThanEquals(Value, Value),
+    Equals(Expr, Expr),
+    NotEquals(Expr, Expr),
+    LessThan(Expr, Expr),
+    GreaterThan(Expr, Expr),
+    LessThanEquals(Expr, Expr),
+    GreaterThanEquals(Expr, Expr),
 }
 
 impl Compare {
-    pub fn rewrite(&self) -> String {
+    fn operands_mut(&mut self) -> (&mut Expr, &mut Expr) {
         match self {
-            Compare::Equals(left, right) => format!("{} == {}", left.rewrite(), right.rewrite()),
-            Compare::NotEquals(left, right) => {
-                format!("{} != {}", left.rewrite(), right.rewrite())
-            }
-            Compare::LessThan(left, right) => {
-                format!("{} < {}", left.rewrite(), right.rewrite())
-            }
-            Compare::GreaterThan(left, right) => {
-                format!("{} > {}", left.rewrite(), right.rewrite())
-            }
-            Compare::LessThanEquals(left, right) => {
-                format!("{} <= {}", left.rewrite(), right.rewrite())
-            }
-            Compare::GreaterThanEquals(left, right) => {
-                format!("{} >= {}", left.rewrite(), right.rewrite())
-            }
+            Compare::Equals(left, right)
+            | Compare::NotEquals(left, right)
+            | Compare::LessThan(left, right)
+            | Compare::GreaterThan(left, right)
+            | Compare::LessThanEquals(left, right)
+            | Compare::GreaterThanEquals(left, right) => (left, right),
         }
     }
+
+    pub fn rewrite(&self) -> String {
+        let (left, right, operator) = match self {
+            Compare::Equals(left, right) => (left, right, "=="),
+            Compare::NotEquals(left, right) => (left, right, "!="),
+            Compare::LessThan(left, right) => (left, right, "<"),
+            Compare::GreaterThan(left, right) => (left, right, ">"),
+            Compare::LessThanEquals(left, right) => (left, right, "<="),
+            Compare::GreaterThanEquals(left, right) => (left, right, ">="),
+        };
+
+        format!(
+            "{} {} {}",
+            left.rewrite_operand(4, true),
+            operator,
+            right.rewrite_operand(4, true)
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -98,26 +287,55 @@ impl CallTarget {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum StatementContext {
-    AssignLiteral(String, Value),
-    AssignBinOp(String, BinOp),
-    AssignCall(String, CallTarget, Vec<Value>),
-    GotoDef(String),
+    AssignExpr(String, Expr),
+    AssignCall(String, CallTarget, Vec<Expr>),
+    AssignIndex(String, Vec<Expr>, Expr),
+    AssignCallLabel(String, String, Vec<Expr>),
+    // `a, _, c = expr`, where `_` (stored as `None`) discards its value.
+    Unpack(Vec<Option<String>>, Expr),
+    GotoDef(String, Vec<String>),
     Goto(String),
-    GotoIf(String, Compare),
-    Call(CallTarget, Vec<Value>),
-    CallLabel(String),
-    Ret,
+    GotoIf(String, Expr),
+    // `match value { 1 => one, "a" => letter, _ => other }` jumps to the label of the first arm
+    // equal to the value, or to the `_` arm if there is one.
+    Match(Expr, Vec<(Value, String)>, Option<String>),
+    Call(CallTarget, Vec<Expr>),
+    CallLabel(String, Vec<Expr>),
+    Global(Vec<String>),
+    FuncDef(CallTarget, Vec<String>),
+    FuncEnd,
+    Ret(Option<Expr>),
     EOS,
 }
 
 impl StatementContext {
-    pub fn rewrite(&self) -> String {
+    pub fn exprs_mut(&mut self) -> Vec<&mut Expr> {
         match self {
-            StatementContext::AssignLiteral(identifier, value) => {
-                format!("{} = {}", identifier, value.rewrite())
+            StatementContext::AssignExpr(_, expr)
+            | StatementContext::Unpack(_, expr)
+            | StatementContext::GotoIf(_, expr)
+            | StatementContext::Match(expr, _, _) => vec![expr],
+            StatementContext::AssignCall(_, _, args)
+            | StatementContext::AssignCallLabel(_, _, args)
+            | StatementContext::Call(_, args)
+            | StatementContext::CallLabel(_, args) => args.iter_mut().collect(),
+            StatementContext::AssignIndex(_, indices, value) => {
+                indices.iter_mut().chain(std::iter::once(value)).collect()
             }
-            StatementContext::AssignBinOp(identifier, binop) => {
-                format!("{} = {}", identifier, binop.rewrite())
+            StatementContext::Ret(value) => value.iter_mut().collect(),
+            StatementContext::GotoDef(_, _)
+            | StatementContext::Goto(_)
+            | StatementContext::Global(_)
+            | StatementContext::FuncDef(_, _)
+            | StatementContext::FuncEnd
+            | StatementContext::EOS => Vec::new(),
+        }
+    }
+
+    pub fn rewrite(&self) -> String {
+        match self {
+            StatementContext::AssignExpr(identifier, expr) => {
+                format!("{} = {}", identifier, expr.rewrite())
             }
             StatementContext::AssignCall(identifier, call_target, args) => {
                 format!(
@@ -130,11 +348,54 @@ impl StatementContext {
                         .join(" ")
                 )
             }
-            StatementContext::GotoDef(identifier) => format!("~{}", identifier),
+            StatementContext::AssignIndex(identifier, indices, value) => {
+                format!(
+                    "{}{} = {}",
+                    identifier,
+                    indices
+                        .iter()
+                        .map(|index| format!("[{}]", index.rewrite()))
+                        .collect::<String>(),
+                    value.rewrite()
+                )
+            }
+            StatementContext::AssignCallLabel(identifier, label, args) => {
+                let mut parts = vec![format!("{} = call {}", identifier, label)];
+                parts.extend(args.iter().map(|arg| arg.rewrite()));
+                parts.join(" ")
+            }
+            StatementContext::Unpack(targets, expr) => {
+                format!(
+                    "{} = {}",
+                    targets
+                        .iter()
+                        .map(|target| target.as_deref().unwrap_or("_"))
+                        .collect::<Vec<&str>>()
+                        .join(", "),
+                    expr.rewrite()
+                )
+            }
+            StatementContext::GotoDef(identifier, params) => {
+                let mut parts = vec![format!("~{}", identifier)];
+                parts.extend(params.iter().cloned());
+                parts.join(" ")
+            }
             StatementContext::Goto(identifier) => format!("goto {}", identifier),
             StatementContext::GotoIf(identifier, compare) => {
                 format!("goto {} if {}", identifier, compare.rewrite())
             }
+            StatementContext::Match(value, arms, default) => {
+                let mut parts = arms
+                    .iter()
+                    .map(|(value, label)| format!("{} => {}", value.rewrite(), label))
+                    .collect::<Vec<String>>();
+
+                if let Some(default) = default {
+                    parts.push(format!("_ => {}", default));
+                }
+
+                format!("match {} {{ {} }}", value.rewrite(), parts.join(", "))
+            }
             StatementContext::Call(call_target, args) => {
                 format!(
                     "{} {}",
@@ -145,8 +406,26 @@ impl StatementContext {
                         .join(" ")
                 )
             }
-            StatementContext::CallLabel(identifier) => format!("call {}", identifier),
-            StatementContext::Ret => "ret".to_string(),
+            StatementContext::CallLabel(identifier, args) => {
+                let mut parts = vec![format!("call {}", identifier)];
+                parts.extend(args.iter().map(|arg| arg.rewrite()));
+                parts.join(" ")
+            }
+            StatementContext::Global(identifiers) => format!("global {}", identifiers.join(" ")),
+            StatementContext::FuncDef(call_target, params) => {
+                let mut parts = vec![format!(
+                    "fn {}:{}",
+                    call_target.module, call_target.function
+                )];
+                parts.extend(params.iter().cloned());
+                parts.push("{".to_string());
+                parts.join(" ")
+            }
+            StatementContext::FuncEnd => "}".to_string(),
+            StatementContext::Ret(value) => match value {
+                Some(value) => format!("ret {}", value.rewrite()),
+                None => "ret".to_string(),
+            },
             StatementContext::EOS => "".to_string(),
         }
     }
@@ -156,13 +435,18 @@ impl StatementContext {
 pub struct Statement {
     pub line: usize,
     pub file: String,
+    // The line a statement was lowered from, shown in errors instead of the generated code.
+    pub source: Option<String>,
 
     pub context: StatementContext,
 }
 
 impl Statement {
     pub fn error(&self, message: String) -> crate::errors::Error {
-        let line = self.context.rewrite();
+        let line = match &self.source {
+            Some(source) => source.clone(),
+            None => self.context.rewrite(),
+        };
 
         crate::errors::Error::new(
             self.line,
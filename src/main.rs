@@ -1,6 +1,9 @@
-use std::fs;
+use std::{fs, path::PathBuf};
 
-use stdlib::{array, convert, file, io, iter, math, runtime, stack, std as aoclstd, string, test};
+use stdlib::{
+    array, convert, counter, file, io, iter, map, math, runtime, set, stack, std as aoclstd,
+    string, test,
+};
 
 use crate::vvm::vvm_run;
 
@@ -12,14 +15,39 @@ mod vvm;
 
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
-    if args.len() < 2 {
-        println!("Usage: {} <file>", args[0]);
-        return;
+
+    let mut lib_paths = Vec::<PathBuf>::new();
+    let mut file = None;
+    let mut i = 1;
+
+    // Options come before the file; anything after it is left for @std:getargs.
+    while i < args.len() {
+        if args[i] == "--lib-path" && i + 1 < args.len() {
+            lib_paths.push(PathBuf::from(&args[i + 1]));
+            i += 2;
+            continue;
+        }
+
+        file = Some(args[i].clone());
+        break;
+    }
+
+    let file = match file {
+        Some(file) => file,
+        None => {
+            println!("Usage: {} [--lib-path <dir>]... <file>", args[0]);
+            return;
+        }
+    };
+
+    if let Some(paths) = std::env::var_os("AOCL_PATH") {
+        lib_paths.extend(std::env::split_paths(&paths));
     }
 
-    let data = fs::read_to_string(args[1].clone()).unwrap() + "\n";
+    let data = fs::read_to_string(file.clone()).unwrap() + "\n";
 
-    let mut lexer = frontend::lexer::Lexer::new(args[1].clone(), data);
+    let mut lexer = frontend::lexer::Lexer::new(file, data);
+    lexer.lib_paths = lib_paths;
     let tokens = lexer.tokenise();
 
     if let Err(e) = tokens {
@@ -32,25 +60,36 @@ fn main() {
     let mut parser = frontend::parser::Parser::new(tokens);
     let program = parser.parse();
 
+    for warning in &parser.warnings {
+        println!("{}", warning);
+    }
+
     if let Err(e) = program {
         println!("{}", e);
         return;
     }
 
     if std::env::var("AOCL_VVM").is_ok() {
-        vvm_run(&program.as_ref().unwrap());
+        if let Err(e) = vvm_run(program.as_ref().unwrap()) {
+            println!("{}", e);
+        }
+
         return;
     }
 
     let mut vm = vm::VM::new(program.unwrap());
+    vm.args = args[i..].to_vec();
 
     array::register(&mut vm);
     convert::register(&mut vm);
+    counter::register(&mut vm);
     file::register(&mut vm);
     io::register(&mut vm);
     iter::register(&mut vm);
+    map::register(&mut vm);
     math::register(&mut vm);
     runtime::register(&mut vm);
+    set::register(&mut vm);
     stack::register(&mut vm);
     aoclstd::register(&mut vm);
     string::register(&mut vm);
@@ -1,4 +1,4 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
 
 use crate::vm::{VMValue, VM};
 
@@ -39,8 +39,8 @@ pub fn array_get(
 }
 
 pub fn array_pop(
-    vm: &mut VM,
-    idts: Vec<Option<String>>,
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
     if args.len() != 1 {
@@ -52,25 +52,17 @@ pub fn array_pop(
         _ => return Err(format!("expected array, got {}", args[0].name())),
     };
 
-    if array.borrow().len() == 0 {
-        return Err(format!("cannot pop from empty array"));
+    // Arrays are shared, so changing one in place is seen through every name and index that
+    // refers to it, including literals and `grid[0]`, which have no name to rebind.
+    match array.borrow_mut().pop() {
+        Some(popped) => Ok(Some(popped)),
+        None => Err("cannot pop from empty array".to_string()),
     }
-
-    let new_array = array.borrow()[..array.borrow().len() - 1].to_vec();
-
-    vm.variables.insert(
-        idts[0].clone().unwrap(),
-        VMValue::Array(Rc::new(RefCell::new(new_array))),
-    );
-
-    let popped = array.borrow()[array.borrow().len() - 1].clone();
-
-    Ok(Some(popped))
 }
 
 pub fn array_popat(
-    vm: &mut VM,
-    idts: Vec<Option<String>>,
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
     if args.len() != 2 {
@@ -91,18 +83,14 @@ pub fn array_popat(
         return Err(format!("index out of bounds: {}", index));
     }
 
-    let new_array = array.clone();
-    new_array.borrow_mut().remove(*index as usize);
+    let popped = array.borrow_mut().remove(*index as usize);
 
-    vm.variables
-        .insert(idts[0].clone().unwrap(), VMValue::Array(new_array));
-
-    Ok(Some(array.borrow()[*index as usize].clone()))
+    Ok(Some(popped))
 }
 
 pub fn array_push(
-    vm: &mut VM,
-    idts: Vec<Option<String>>,
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
     if args.len() != 2 {
@@ -114,12 +102,7 @@ pub fn array_push(
         _ => return Err(format!("expected array, got {}", args[0].name())),
     };
 
-    let value = args[1].clone();
-
-    array.borrow_mut().push(value.clone());
-
-    vm.variables
-        .insert(idts[0].clone().unwrap(), VMValue::Array(array.clone()));
+    array.borrow_mut().push(args[1].clone());
 
     Ok(None)
 }
@@ -153,8 +136,8 @@ pub fn array_index(
 }
 
 pub fn array_reverse(
-    vm: &mut VM,
-    idts: Vec<Option<String>>,
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
     if args.len() != 1 {
@@ -166,18 +149,14 @@ pub fn array_reverse(
         _ => return Err(format!("expected array, got {}", args[0].name())),
     };
 
-    let new_array = array.clone();
-    new_array.borrow_mut().reverse();
-
-    vm.variables
-        .insert(idts[0].clone().unwrap(), VMValue::Array(new_array));
+    array.borrow_mut().reverse();
 
     Ok(None)
 }
 
 pub fn array_sort(
-    vm: &mut VM,
-    idts: Vec<Option<String>>,
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
     if args.len() != 1 {
@@ -189,11 +168,22 @@ pub fn array_sort(
         _ => return Err(format!("expected array, got {}", args[0].name())),
     };
 
-    let new_array = array.clone();
-    new_array.borrow_mut().sort();
+    // Sort a copy, so a failed comparison leaves the array as it was.
+    let mut sorted = array.borrow().clone();
+    let mut error = None;
+
+    sorted.sort_by(|left, right| {
+        left.compare(right).unwrap_or_else(|err| {
+            error.get_or_insert(err);
+            Ordering::Equal
+        })
+    });
+
+    if let Some(err) = error {
+        return Err(err);
+    }
 
-    vm.variables
-        .insert(idts[0].clone().unwrap(), VMValue::Array(new_array));
+    *array.borrow_mut() = sorted;
 
     Ok(None)
 }
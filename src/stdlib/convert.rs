@@ -1,3 +1,5 @@
+use num_bigint::BigInt;
+
 use crate::vm::{VMValue, VM};
 
 pub fn convert_atoi(
@@ -14,12 +16,12 @@ pub fn convert_atoi(
         _ => return Err(format!("expected string, got {}", args[0].name())),
     };
 
-    let int = match string.parse::<i64>() {
+    let int = match string.parse::<BigInt>() {
         Ok(int) => int,
         Err(err) => return Err(format!("failed to parse int: {}", err)),
     };
 
-    Ok(Some(VMValue::Int(int)))
+    Ok(Some(VMValue::from_bigint(int)))
 }
 
 pub fn convert_itoa(
@@ -32,7 +34,7 @@ pub fn convert_itoa(
     }
 
     let int = match &args[0] {
-        VMValue::Int(int) => int,
+        VMValue::Int(_) | VMValue::BigInt(_) => &args[0],
         _ => return Err(format!("expected int, got {}", args[0].name())),
     };
 
@@ -0,0 +1,128 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{expect_args, expect_args_between};
+use crate::vm::{Map, VMValue, VM};
+
+// Counters are plain maps from values to int counts, so the map module works on them too.
+fn get_counter(value: &VMValue) -> Result<Rc<RefCell<Map>>, String> {
+    match value {
+        VMValue::Map(map) => Ok(map.clone()),
+        _ => Err(format!("expected counter, got {}", value.name())),
+    }
+}
+
+fn get_count(counter: &Map, key: &VMValue) -> Result<i64, String> {
+    match counter.get(key) {
+        Some(VMValue::Int(count)) => Ok(*count),
+        Some(value) => Err(format!("expected int count, got {}", value.name())),
+        None => Ok(0),
+    }
+}
+
+// @counter:new [items]
+pub fn counter_new(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args_between(&args, 0, 1)?;
+
+    let mut counter = Map::new();
+
+    if let Some(items) = args.first() {
+        for item in items.items()? {
+            let key = item.to_key()?;
+            let count = get_count(&counter, &key)?;
+
+            let count = count.checked_add(1).ok_or("count overflowed")?;
+
+            counter.insert(key, VMValue::Int(count));
+        }
+    }
+
+    Ok(Some(VMValue::Map(Rc::new(RefCell::new(counter)))))
+}
+
+// @counter:add counter value [count]
+pub fn counter_add(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args_between(&args, 2, 3)?;
+
+    let counter = get_counter(&args[0])?;
+    let key = args[1].to_key()?;
+
+    let amount = match args.get(2) {
+        Some(VMValue::Int(amount)) => *amount,
+        Some(value) => return Err(format!("expected int, got {}", value.name())),
+        None => 1,
+    };
+
+    let count = get_count(&counter.borrow(), &key)?
+        .checked_add(amount)
+        .ok_or("count overflowed")?;
+    counter.borrow_mut().insert(key, VMValue::Int(count));
+
+    Ok(Some(VMValue::Int(count)))
+}
+
+// @counter:get counter value
+pub fn counter_get(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let counter = get_counter(&args[0])?;
+    let count = get_count(&counter.borrow(), &args[1].to_key()?)?;
+
+    Ok(Some(VMValue::Int(count)))
+}
+
+// @counter:most_common counter [n]
+pub fn counter_most_common(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args_between(&args, 1, 2)?;
+
+    let counter = get_counter(&args[0])?;
+
+    let mut counts = Vec::new();
+
+    for key in counter.borrow().keys() {
+        counts.push((key.to_key()?, get_count(&counter.borrow(), key)?));
+    }
+
+    // The sort is stable, so equal counts stay in the order they were first added.
+    counts.sort_by(|(_, left), (_, right)| right.cmp(left));
+
+    if let Some(n) = args.get(1) {
+        match n {
+            VMValue::Int(n) if *n >= 0 => counts.truncate(*n as usize),
+            _ => return Err(format!("expected non-negative int, got {}", n)),
+        }
+    }
+
+    let pairs = counts
+        .into_iter()
+        .map(|(key, count)| VMValue::Array(Rc::new(RefCell::new(vec![key, VMValue::Int(count)]))))
+        .collect();
+
+    Ok(Some(VMValue::Array(Rc::new(RefCell::new(pairs)))))
+}
+
+pub fn register(vm: &mut VM) {
+    vm.register("counter".to_string(), "new".to_string(), counter_new);
+    vm.register("counter".to_string(), "add".to_string(), counter_add);
+    vm.register("counter".to_string(), "get".to_string(), counter_get);
+    vm.register(
+        "counter".to_string(),
+        "most_common".to_string(),
+        counter_most_common,
+    );
+}
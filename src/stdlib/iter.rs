@@ -1,88 +1,210 @@
-use crate::vm::{VMValue, VM};
+use crate::{
+    frontend::parser::StatementContext,
+    vm::{Loop, LoopItems, VMValue, VM},
+};
+
+// Returns the name of the `@iter` function called by the statement at `index`, if any.
+fn iter_call(vm: &VM, index: usize) -> Option<&str> {
+    match &vm.statements[index].context {
+        StatementContext::Call(target, _) if target.module == "iter" => {
+            Some(target.function.as_str())
+        }
+        _ => None,
+    }
+}
+
+// Finds the `@iter:end` closing the loop that starts at `start`, skipping nested loops. The
+// statements never change, so the result is remembered for the next time the loop starts.
+fn find_end(vm: &mut VM, start: usize) -> Result<usize, String> {
+    if let Some(end) = vm.loop_ends.get(&start) {
+        return Ok(*end);
+    }
+
+    let mut depth = 0;
+
+    for index in start + 1..vm.statements.len() {
+        match iter_call(vm, index) {
+            Some("range" | "each" | "iterate") => depth += 1,
+            Some("end") if depth == 0 => {
+                vm.loop_ends.insert(start, index);
+                return Ok(index);
+            }
+            Some("end") => depth -= 1,
+            _ => {}
+        }
+    }
+
+    Err("loop has no matching @iter:end".to_string())
+}
+
+// A loop left with `goto` is still on the stack, so any loop of the current call that doesn't
+// contain the running statement is dropped before the stack is used.
+fn drop_left_loops(vm: &mut VM) {
+    while let Some(state) = vm.loops.last() {
+        if state.depth != vm.frames.len() || (state.start < vm.index && vm.index <= state.end) {
+            break;
+        }
+
+        vm.loops.pop();
+    }
+}
+
+fn begin(vm: &mut VM, var: String, items: LoopItems) -> Result<Option<VMValue>, String> {
+    drop_left_loops(vm);
+
+    let end = find_end(vm, vm.index)?;
 
-// @iter:iterate "name" "varname" start end
-pub fn iter_iterate(
+    let mut state = Loop {
+        var,
+        items,
+        start: vm.index,
+        end,
+        depth: vm.frames.len(),
+    };
+
+    match state.next() {
+        Some(value) => {
+            vm.set_var(state.var.clone(), value);
+            vm.loops.push(state);
+        }
+        // Nothing to iterate over, so skip straight past the loop.
+        None => vm.index = state.end,
+    }
+
+    Ok(None)
+}
+
+fn current(vm: &mut VM) -> Result<usize, String> {
+    drop_left_loops(vm);
+
+    match vm.loops.last() {
+        Some(state) if state.depth == vm.frames.len() => Ok(vm.loops.len() - 1),
+        _ => Err("not inside an @iter loop".to_string()),
+    }
+}
+
+// @iter:range "var" start end [step]
+pub fn iter_range(
     vm: &mut VM,
     _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
-    if args.len() != 3 {
-        return Err(format!("expected 3 arguments, got {}", args.len()));
+    if args.len() != 3 && args.len() != 4 {
+        return Err(format!("expected 3 or 4 arguments, got {}", args.len()));
     }
 
-    let varname = match args[0].clone() {
-        VMValue::String(varname) => varname,
-        _ => return Err(format!("expected string, got {}", args[1])),
+    let var = match args[0].clone() {
+        VMValue::String(var) => var,
+        _ => return Err(format!("expected string, got {}", args[0])),
     };
 
     let start = match args[1] {
         VMValue::Int(start) => start,
-        _ => return Err(format!("expected int, got {}", args[2])),
+        _ => return Err(format!("expected int, got {}", args[1])),
     };
 
-    let mut end = match args[2] {
+    let end = match args[2] {
         VMValue::Int(end) => end,
-        _ => return Err(format!("expected int, got {}", args[3])),
+        _ => return Err(format!("expected int, got {}", args[2])),
     };
 
-    end -= 1;
+    let step = match args.get(3) {
+        Some(VMValue::Int(0)) => return Err("step cannot be 0".to_string()),
+        Some(VMValue::Int(step)) => *step,
+        Some(step) => return Err(format!("expected int, got {}", step)),
+        None => 1,
+    };
+
+    begin(
+        vm,
+        var,
+        LoopItems::Range {
+            next: start,
+            end,
+            step,
+        },
+    )
+}
 
-    if start > end {
-        return Err(format!("start must be less than end"));
+// @iter:each "var" collection
+pub fn iter_each(
+    vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    if args.len() != 2 {
+        return Err(format!("expected 2 arguments, got {}", args.len()));
     }
 
-    vm.variables.insert(varname.clone(), VMValue::Int(start));
-    vm.variables.insert(
-        format!("@internal:iter:end:{}", varname.clone()),
-        VMValue::Int(end),
-    );
+    let var = match args[0].clone() {
+        VMValue::String(var) => var,
+        _ => return Err(format!("expected string, got {}", args[0])),
+    };
 
-    vm.gotos
-        .insert(format!("@internal:iter:{}", varname), vm.index);
+    // Iterate over a snapshot, so changing an array inside its loop doesn't affect the loop.
+    let values = args[1].items()?;
 
-    Ok(None)
+    begin(vm, var, LoopItems::Values(values, 0))
 }
 
+// @iter:end ["var"]
 pub fn iter_end(
     vm: &mut VM,
     _idts: Vec<Option<String>>,
     args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
-    if args.len() != 1 {
-        return Err(format!("expected 1 argument, got {}", args.len()));
+    if args.len() > 1 {
+        return Err(format!("expected at most 1 argument, got {}", args.len()));
     }
 
-    let varname = match args[0].clone() {
-        VMValue::String(iter) => iter,
-        _ => return Err(format!("expected string, got {}", args[0])),
-    };
+    let index = current(vm)?;
 
-    let current = match vm.variables.get(&varname.clone()) {
-        Some(VMValue::Int(current)) => current,
-        _ => return Err("invalid iter for current".to_string()),
-    };
+    if let Some(name) = args.first() {
+        let var = &vm.loops[index].var;
 
-    let end = match vm.variables.get(&format!("@internal:iter:end:{}", varname)) {
-        Some(VMValue::Int(end)) => end,
-        _ => return Err("invalid iter for end".to_string()),
-    };
+        match name {
+            VMValue::String(name) if name == var => {}
+            _ => return Err(format!("innermost loop is over '{}', not {}", var, name)),
+        }
+    }
+
+    match vm.loops[index].next() {
+        Some(value) => {
+            let state = &vm.loops[index];
 
-    if current >= end {
-        vm.gotos.remove(&format!("@internal:iter:{}", varname));
-        vm.variables
-            .remove(&format!("@internal:iter:end:{}", varname));
-        vm.variables.remove(&varname);
-        return Ok(None);
-    } else {
-        vm.variables
-            .insert(varname.clone(), VMValue::Int(current + 1));
-        vm.index = vm.gotos[&format!("@internal:iter:{}", varname)];
+            vm.index = state.start;
+            vm.set_var(state.var.clone(), value);
+        }
+        None => {
+            vm.loops.pop();
+        }
     }
 
     Ok(None)
 }
 
+// @iter:stop
+pub fn iter_stop(
+    vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    if !args.is_empty() {
+        return Err(format!("expected 0 arguments, got {}", args.len()));
+    }
+
+    current(vm)?;
+
+    let state = vm.loops.pop().unwrap();
+    vm.index = state.end;
+
+    Ok(None)
+}
+
 pub fn register(vm: &mut VM) {
-    vm.register("iter".to_string(), "iterate".to_string(), iter_iterate);
+    vm.register("iter".to_string(), "range".to_string(), iter_range);
+    vm.register("iter".to_string(), "iterate".to_string(), iter_range);
+    vm.register("iter".to_string(), "each".to_string(), iter_each);
     vm.register("iter".to_string(), "end".to_string(), iter_end);
+    vm.register("iter".to_string(), "stop".to_string(), iter_stop);
 }
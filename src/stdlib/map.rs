@@ -0,0 +1,173 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{expect_args, expect_args_between};
+use crate::vm::{Map, VMValue, VM};
+
+fn get_map(value: &VMValue) -> Result<Rc<RefCell<Map>>, String> {
+    match value {
+        VMValue::Map(map) => Ok(map.clone()),
+        _ => Err(format!("expected map, got {}", value.name())),
+    }
+}
+
+fn to_array(values: Vec<VMValue>) -> VMValue {
+    VMValue::Array(Rc::new(RefCell::new(values)))
+}
+
+// @map:new [key value]...
+pub fn map_new(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    if !args.len().is_multiple_of(2) {
+        return Err(format!(
+            "expected key and value pairs, got {} arguments",
+            args.len()
+        ));
+    }
+
+    let mut map = Map::new();
+
+    for pair in args.chunks(2) {
+        map.insert(pair[0].to_key()?, pair[1].clone());
+    }
+
+    Ok(Some(VMValue::Map(Rc::new(RefCell::new(map)))))
+}
+
+// @map:get map key [default]
+pub fn map_get(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args_between(&args, 2, 3)?;
+
+    let map = get_map(&args[0])?;
+    let key = args[1].to_key()?;
+    let default = args.get(2).cloned().unwrap_or(VMValue::None);
+
+    let value = map.borrow().get(&key).cloned().unwrap_or(default);
+
+    Ok(Some(value))
+}
+
+// @map:set map key value
+pub fn map_set(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 3)?;
+
+    let map = get_map(&args[0])?;
+    map.borrow_mut().insert(args[1].to_key()?, args[2].clone());
+
+    Ok(None)
+}
+
+// @map:has map key
+pub fn map_has(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let map = get_map(&args[0])?;
+    let has = map.borrow().contains_key(&args[1].to_key()?);
+
+    Ok(Some(VMValue::Bool(has)))
+}
+
+// @map:remove map key
+pub fn map_remove(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let map = get_map(&args[0])?;
+    // Keep the remaining keys in insertion order.
+    let removed = map.borrow_mut().shift_remove(&args[1].to_key()?);
+
+    Ok(Some(removed.unwrap_or(VMValue::None)))
+}
+
+// @map:keys map
+pub fn map_keys(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 1)?;
+
+    let map = get_map(&args[0])?;
+    let keys = map
+        .borrow()
+        .keys()
+        .map(VMValue::to_key)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(to_array(keys)))
+}
+
+// @map:values map
+pub fn map_values(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 1)?;
+
+    let map = get_map(&args[0])?;
+    let values = map.borrow().values().cloned().collect();
+
+    Ok(Some(to_array(values)))
+}
+
+// @map:items map
+pub fn map_items(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 1)?;
+
+    let map = get_map(&args[0])?;
+    let items = map
+        .borrow()
+        .iter()
+        .map(|(key, value)| Ok(to_array(vec![key.to_key()?, value.clone()])))
+        .collect::<Result<_, String>>()?;
+
+    Ok(Some(to_array(items)))
+}
+
+// @map:len map
+pub fn map_len(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 1)?;
+
+    let map = get_map(&args[0])?;
+    let len = map.borrow().len();
+
+    Ok(Some(VMValue::Int(len as i64)))
+}
+
+pub fn register(vm: &mut VM) {
+    vm.register("map".to_string(), "new".to_string(), map_new);
+    vm.register("map".to_string(), "get".to_string(), map_get);
+    vm.register("map".to_string(), "set".to_string(), map_set);
+    vm.register("map".to_string(), "has".to_string(), map_has);
+    vm.register("map".to_string(), "remove".to_string(), map_remove);
+    vm.register("map".to_string(), "keys".to_string(), map_keys);
+    vm.register("map".to_string(), "values".to_string(), map_values);
+    vm.register("map".to_string(), "items".to_string(), map_items);
+    vm.register("map".to_string(), "len".to_string(), map_len);
+}
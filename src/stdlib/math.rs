@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::vm::{VMValue, VM};
 
 pub fn math_sum(
@@ -14,18 +16,16 @@ pub fn math_sum(
         _ => return Err(format!("expected array, got {}", args[0].name())),
     };
 
-    let mut sum = 0;
+    let mut sum = VMValue::Int(0);
 
     for part in array.borrow().clone().into_iter() {
-        let int = match part {
-            VMValue::Int(int) => int,
+        match part {
+            VMValue::Int(_) | VMValue::BigInt(_) => sum = sum.add(&part)?,
             _ => return Err(format!("expected int in array, got {}", part.name())),
         };
-
-        sum += int;
     }
 
-    Ok(Some(VMValue::Int(sum)))
+    Ok(Some(sum))
 }
 
 pub fn math_max(
@@ -42,23 +42,41 @@ pub fn math_max(
         _ => return Err(format!("expected array, got {}", args[0].name())),
     };
 
-    let mut max = std::i64::MIN;
+    let mut max = VMValue::Int(i64::MIN);
 
     for part in array.borrow().clone().into_iter() {
-        let int = match part {
-            VMValue::Int(int) => int,
+        match part {
+            VMValue::Int(_) | VMValue::BigInt(_) => {
+                if part.compare(&max)?.is_gt() {
+                    max = part;
+                }
+            }
             _ => return Err(format!("expected int in array, got {}", part.name())),
         };
+    }
+
+    Ok(Some(max))
+}
 
-        if int > max {
-            max = int;
-        }
+// @math:divmod a b
+pub fn math_divmod(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    if args.len() != 2 {
+        return Err(format!("expected 2 arguments, got {}", args.len()));
     }
 
-    Ok(Some(VMValue::Int(max)))
+    let quotient = args[0].euclid_div(&args[1])?;
+    let remainder = args[0].euclid_mod(&args[1])?;
+
+    // Returned as a tuple so it can be unpacked with `q, r = @math:divmod a b`.
+    Ok(Some(VMValue::Tuple(Rc::new(vec![quotient, remainder]))))
 }
 
 pub fn register(vm: &mut VM) {
     vm.register("math".to_string(), "sum".to_string(), math_sum);
     vm.register("math".to_string(), "max".to_string(), math_max);
+    vm.register("math".to_string(), "divmod".to_string(), math_divmod);
 }
@@ -1,11 +1,46 @@
 pub mod array;
 pub mod convert;
+pub mod counter;
 pub mod file;
 pub mod io;
 pub mod iter;
+pub mod map;
 pub mod math;
 pub mod runtime;
+pub mod set;
 pub mod stack;
 pub mod std;
 pub mod string;
 pub mod test;
+
+use crate::vm::VMValue;
+
+pub(crate) fn expect_args(args: &[VMValue], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!(
+            "expected {} argument{}, got {}",
+            count,
+            if count == 1 { "" } else { "s" },
+            args.len()
+        ));
+    }
+
+    Ok(())
+}
+
+// For functions with optional trailing arguments, e.g. "expected 2 or 3 arguments".
+pub(crate) fn expect_args_between(args: &[VMValue], min: usize, max: usize) -> Result<(), String> {
+    if args.len() < min || args.len() > max {
+        let range = if max == min + 1 { "or" } else { "to" };
+
+        return Err(format!(
+            "expected {} {} {} arguments, got {}",
+            min,
+            range,
+            max,
+            args.len()
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{expect_args, expect_args_between};
+use crate::vm::{Set, VMValue, VM};
+
+fn get_set(value: &VMValue) -> Result<Rc<RefCell<Set>>, String> {
+    match value {
+        VMValue::Set(set) => Ok(set.clone()),
+        _ => Err(format!("expected set, got {}", value.name())),
+    }
+}
+
+fn to_set(set: Set) -> VMValue {
+    VMValue::Set(Rc::new(RefCell::new(set)))
+}
+
+// @set:new [items]
+pub fn set_new(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args_between(&args, 0, 1)?;
+
+    let mut set = Set::new();
+
+    if let Some(items) = args.first() {
+        for item in items.items()? {
+            set.insert(item.to_key()?);
+        }
+    }
+
+    Ok(Some(to_set(set)))
+}
+
+// @set:add set value
+pub fn set_add(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let set = get_set(&args[0])?;
+    let added = set.borrow_mut().insert(args[1].to_key()?);
+
+    Ok(Some(VMValue::Bool(added)))
+}
+
+// @set:has set value
+pub fn set_has(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let set = get_set(&args[0])?;
+    let has = set.borrow().contains(&args[1].to_key()?);
+
+    Ok(Some(VMValue::Bool(has)))
+}
+
+// @set:remove set value
+pub fn set_remove(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let set = get_set(&args[0])?;
+    let removed = set.borrow_mut().shift_remove(&args[1].to_key()?);
+
+    Ok(Some(VMValue::Bool(removed)))
+}
+
+// @set:union a b
+pub fn set_union(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let left = get_set(&args[0])?;
+    let right = get_set(&args[1])?;
+    let union = left.borrow().union(&right.borrow()).cloned().collect();
+
+    Ok(Some(to_set(union)))
+}
+
+// @set:intersection a b
+pub fn set_intersection(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let left = get_set(&args[0])?;
+    let right = get_set(&args[1])?;
+    let intersection = left
+        .borrow()
+        .intersection(&right.borrow())
+        .cloned()
+        .collect();
+
+    Ok(Some(to_set(intersection)))
+}
+
+// @set:difference a b
+pub fn set_difference(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 2)?;
+
+    let left = get_set(&args[0])?;
+    let right = get_set(&args[1])?;
+    let difference = left.borrow().difference(&right.borrow()).cloned().collect();
+
+    Ok(Some(to_set(difference)))
+}
+
+// @set:len set
+pub fn set_len(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 1)?;
+
+    let set = get_set(&args[0])?;
+    let len = set.borrow().len();
+
+    Ok(Some(VMValue::Int(len as i64)))
+}
+
+// @set:toarray set
+pub fn set_toarray(
+    _vm: &mut VM,
+    _idts: Vec<Option<String>>,
+    args: Vec<VMValue>,
+) -> Result<Option<VMValue>, String> {
+    expect_args(&args, 1)?;
+
+    let set = get_set(&args[0])?;
+    let array = set
+        .borrow()
+        .iter()
+        .map(VMValue::to_key)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(VMValue::Array(Rc::new(RefCell::new(array)))))
+}
+
+pub fn register(vm: &mut VM) {
+    vm.register("set".to_string(), "new".to_string(), set_new);
+    vm.register("set".to_string(), "add".to_string(), set_add);
+    vm.register("set".to_string(), "has".to_string(), set_has);
+    vm.register("set".to_string(), "remove".to_string(), set_remove);
+    vm.register("set".to_string(), "union".to_string(), set_union);
+    vm.register(
+        "set".to_string(),
+        "intersection".to_string(),
+        set_intersection,
+    );
+    vm.register("set".to_string(), "difference".to_string(), set_difference);
+    vm.register("set".to_string(), "len".to_string(), set_len);
+    vm.register("set".to_string(), "toarray".to_string(), set_toarray);
+}
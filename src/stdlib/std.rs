@@ -192,15 +192,13 @@ pub fn std_all(
 }
 
 pub fn std_getargs(
-    _vm: &mut VM,
+    vm: &mut VM,
     _idts: Vec<Option<String>>,
     _args: Vec<VMValue>,
 ) -> Result<Option<VMValue>, String> {
-    let args: Vec<VMValue> = std::env::args().map(VMValue::String).collect();
+    let args = vm.args.iter().cloned().map(VMValue::String).collect();
 
-    Ok(Some(VMValue::Array(Rc::new(RefCell::new(
-        args[1..].to_vec(),
-    )))))
+    Ok(Some(VMValue::Array(Rc::new(RefCell::new(args)))))
 }
 
 pub fn std_getenv(
@@ -219,7 +217,7 @@ pub fn std_getenv(
 
     let value = match std::env::var(key) {
         Ok(value) => value,
-        Err(_) => return Ok(None),
+        Err(_) => return Ok(Some(VMValue::None)),
     };
 
     Ok(Some(VMValue::String(value)))
@@ -24,7 +24,7 @@ pub fn debugger(vm: &mut VM) {
 
                 let identifier = args[1];
 
-                if let Some(value) = vm.variables.get(identifier) {
+                if let Some(value) = vm.get_var(identifier) {
                     println!("{} = {}", identifier, value);
                 } else {
                     println!("variable not found: {}", identifier);
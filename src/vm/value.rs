@@ -1,23 +1,43 @@
 use core::fmt;
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use indexmap::{IndexMap, IndexSet};
+use num_bigint::BigInt;
+use num_traits::{Euclid, ToPrimitive};
 
 use crate::frontend::parser::Value;
 
-#[derive(Debug, PartialEq, Clone, PartialOrd)]
+pub type Map = IndexMap<VMValue, VMValue>;
+pub type Set = IndexSet<VMValue>;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum VMValue {
+    None,
     Bool(bool),
     Int(i64),
+    // Only holds integers that don't fit in an int; see `VMValue::from_bigint`.
+    BigInt(Rc<BigInt>),
     Float(f64),
     String(String),
     Identifier(Rc<RefCell<String>>),
     Array(Rc<RefCell<Vec<VMValue>>>),
+    Tuple(Rc<Vec<VMValue>>),
+    Map(Rc<RefCell<Map>>),
+    Set(Rc<RefCell<Set>>),
 }
 
 impl fmt::Display for VMValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::None => write!(f, "none"),
             Self::Bool(bool) => write!(f, "{}", bool),
             Self::Int(int) => write!(f, "{}", int),
+            Self::BigInt(int) => write!(f, "{}", int),
             Self::Float(float) => write!(f, "{}", float),
             Self::String(string) => write!(f, "{}", string),
             Self::Identifier(identifier) => write!(f, "{}", identifier.borrow()),
@@ -34,22 +54,74 @@ impl fmt::Display for VMValue {
 
                 write!(f, "]")
             }
+            Self::Tuple(tuple) => {
+                write!(f, "(")?;
+
+                for (i, value) in tuple.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                // A single element tuple keeps its comma so it doesn't read as a group.
+                if tuple.len() == 1 {
+                    write!(f, ",")?;
+                }
+
+                write!(f, ")")
+            }
+            Self::Map(map) => {
+                write!(f, "{{")?;
+
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}: {}", key, value)?;
+                }
+
+                write!(f, "}}")
+            }
+            Self::Set(set) => {
+                write!(f, "{{")?;
+
+                for (i, value) in set.borrow().iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                write!(f, "}}")
+            }
         }
     }
 }
 
 impl Eq for VMValue {}
 
-impl Ord for VMValue {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => left.cmp(right),
-            (Self::Float(left), Self::Float(right)) => left.partial_cmp(right).unwrap(),
-            (Self::Int(left), Self::Float(right)) => (*left as f64).partial_cmp(right).unwrap(),
-            (Self::Float(left), Self::Int(right)) => left.partial_cmp(&(*right as f64)).unwrap(),
-            (Self::String(left), Self::String(right)) => left.cmp(right),
-            (Self::Bool(left), Self::Bool(right)) => left.cmp(right),
-            _ => panic!("cannot compare {} and {}", self.name(), other.name()),
+// Only values accepted by `VMValue::to_key` are ever hashed for real, but every variant needs
+// to hash consistently with `PartialEq`.
+impl Hash for VMValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            Self::None => {}
+            Self::Bool(bool) => bool.hash(state),
+            Self::Int(int) => int.hash(state),
+            Self::BigInt(int) => int.hash(state),
+            Self::Float(float) => float.to_bits().hash(state),
+            Self::String(string) => string.hash(state),
+            Self::Identifier(identifier) => identifier.borrow().hash(state),
+            Self::Array(array) => array.borrow().hash(state),
+            Self::Tuple(tuple) => tuple.hash(state),
+            Self::Map(map) => map.borrow().len().hash(state),
+            Self::Set(set) => set.borrow().len().hash(state),
         }
     }
 }
@@ -58,6 +130,7 @@ impl VMValue {
     pub fn from(value: Value) -> Self {
         match value {
             Value::Bool(bool) => Self::Bool(bool),
+            Value::None => Self::None,
             Value::Int(int) => Self::Int(int),
             Value::Float(float) => Self::Float(float),
             Value::String(string) => Self::String(string),
@@ -65,12 +138,82 @@ impl VMValue {
         }
     }
 
+    // The literal for a value computed at parse time, if it has one.
+    pub fn to_literal(&self) -> Option<Value> {
+        match self {
+            Self::Bool(bool) => Some(Value::Bool(*bool)),
+            Self::None => Some(Value::None),
+            Self::Int(int) => Some(Value::Int(*int)),
+            Self::Float(float) => Some(Value::Float(*float)),
+            Self::String(string) => Some(Value::String(string.clone())),
+            _ => None,
+        }
+    }
+
+    // Wraps an integer result, keeping it as an int if it fits so that equal numbers are always
+    // stored the same way.
+    pub fn from_bigint(int: BigInt) -> Self {
+        match int.to_i64() {
+            Some(int) => Self::Int(int),
+            None => Self::BigInt(Rc::new(int)),
+        }
+    }
+
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Self::Int(int) => Some(BigInt::from(*int)),
+            Self::BigInt(int) => Some(int.as_ref().clone()),
+            _ => None,
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(int) => Some(*int as f64),
+            Self::BigInt(int) => int.to_f64(),
+            Self::Float(float) => Some(*float),
+            _ => None,
+        }
+    }
+
+    // Applies an operation to two integers, moving to big integers if either side is already
+    // big or the result doesn't fit in an int. Gives none unless both sides are integers.
+    fn int_op(
+        &self,
+        other: &Self,
+        small: fn(i64, i64) -> Option<i64>,
+        big: fn(BigInt, BigInt) -> BigInt,
+    ) -> Option<Self> {
+        if let (Self::Int(left), Self::Int(right)) = (self, other) {
+            if let Some(result) = small(*left, *right) {
+                return Some(Self::Int(result));
+            }
+        }
+
+        let left = self.to_bigint()?;
+        let right = other.to_bigint()?;
+
+        Some(Self::from_bigint(big(left, right)))
+    }
+
+    // Gives both sides as floats if either is a float and the other is a number.
+    fn float_op(&self, other: &Self) -> Option<(f64, f64)> {
+        match (self, other) {
+            (Self::Float(_), _) | (_, Self::Float(_)) => Some((self.to_f64()?, other.to_f64()?)),
+            _ => None,
+        }
+    }
+
     pub fn add(&self, other: &Self) -> Result<Self, String> {
+        if let Some(result) = self.int_op(other, i64::checked_add, |left, right| left + right) {
+            return Ok(result);
+        }
+
+        if let Some((left, right)) = self.float_op(other) {
+            return Ok(Self::Float(left + right));
+        }
+
         match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Int(left + right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Float(left + right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Float(*left as f64 + right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Float(left + *right as f64)),
             (Self::Array(left), Self::Array(right)) => {
                 let mut new_array = left.borrow().clone();
                 new_array.extend(right.borrow().clone());
@@ -82,12 +225,13 @@ impl VMValue {
     }
 
     pub fn sub(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Int(left - right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Float(left - right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Float(*left as f64 - right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Float(left - *right as f64)),
-            _ => Err(format!(
+        if let Some(result) = self.int_op(other, i64::checked_sub, |left, right| left - right) {
+            return Ok(result);
+        }
+
+        match self.float_op(other) {
+            Some((left, right)) => Ok(Self::Float(left - right)),
+            None => Err(format!(
                 "cannot subtract {} and {}",
                 self.name(),
                 other.name()
@@ -96,12 +240,13 @@ impl VMValue {
     }
 
     pub fn mul(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Int(left * right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Float(left * right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Float(*left as f64 * right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Float(left * *right as f64)),
-            _ => Err(format!(
+        if let Some(result) = self.int_op(other, i64::checked_mul, |left, right| left * right) {
+            return Ok(result);
+        }
+
+        match self.float_op(other) {
+            Some((left, right)) => Ok(Self::Float(left * right)),
+            None => Err(format!(
                 "cannot multiply {} and {}",
                 self.name(),
                 other.name()
@@ -109,13 +254,38 @@ impl VMValue {
         }
     }
 
+    pub fn neg(&self) -> Result<Self, String> {
+        match self {
+            Self::Int(int) => match int.checked_neg() {
+                Some(int) => Ok(Self::Int(int)),
+                None => Ok(Self::from_bigint(-BigInt::from(*int))),
+            },
+            Self::BigInt(int) => Ok(Self::from_bigint(-int.as_ref().clone())),
+            Self::Float(float) => Ok(Self::Float(-float)),
+            _ => Err(format!("cannot negate {}", self.name())),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Self::Int(int) => *int == 0,
+            Self::Float(float) => *float == 0.0,
+            _ => false,
+        }
+    }
+
     pub fn div(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Int(left / right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Float(left / right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Float(*left as f64 / right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Float(left / *right as f64)),
-            _ => Err(format!(
+        if other.is_zero() && self.to_f64().is_some() {
+            return Err("division by zero".to_string());
+        }
+
+        if let Some(result) = self.int_op(other, i64::checked_div, |left, right| left / right) {
+            return Ok(result);
+        }
+
+        match self.float_op(other) {
+            Some((left, right)) => Ok(Self::Float(left / right)),
+            None => Err(format!(
                 "cannot divide {} and {}",
                 self.name(),
                 other.name()
@@ -124,12 +294,17 @@ impl VMValue {
     }
 
     pub fn mod_(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Int(left % right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Float(left % right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Float(*left as f64 % right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Float(left % *right as f64)),
-            _ => Err(format!(
+        if other.is_zero() && self.to_f64().is_some() {
+            return Err("modulo by zero".to_string());
+        }
+
+        if let Some(result) = self.int_op(other, i64::checked_rem, |left, right| left % right) {
+            return Ok(result);
+        }
+
+        match self.float_op(other) {
+            Some((left, right)) => Ok(Self::Float(left % right)),
+            None => Err(format!(
                 "cannot modulo {} and {}",
                 self.name(),
                 other.name()
@@ -137,106 +312,373 @@ impl VMValue {
         }
     }
 
-    pub fn equals(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Bool(left == right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Bool(left == right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Bool(*left as f64 == *right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Bool(left == &(*right as f64))),
-            (Self::String(left), Self::String(right)) => Ok(Self::Bool(left == right)),
-            (Self::Bool(left), Self::Bool(right)) => Ok(Self::Bool(left == right)),
-            _ => Err(format!(
-                "cannot compare equality between {} and {}",
+    // Division rounding so that the remainder from `euclid_mod` is never negative.
+    pub fn euclid_div(&self, other: &Self) -> Result<Self, String> {
+        if other.is_zero() && self.to_f64().is_some() {
+            return Err("division by zero".to_string());
+        }
+
+        if let Some(result) = self.int_op(other, i64::checked_div_euclid, |left, right| {
+            Euclid::div_euclid(&left, &right)
+        }) {
+            return Ok(result);
+        }
+
+        match self.float_op(other) {
+            Some((left, right)) => Ok(Self::Float(left.div_euclid(right))),
+            None => Err(format!(
+                "cannot divide {} and {}",
                 self.name(),
                 other.name()
             )),
         }
     }
 
-    pub fn not_equals(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Bool(left != right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Bool(left != right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Bool(*left as f64 != *right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Bool(left != &(*right as f64))),
-            (Self::String(left), Self::String(right)) => Ok(Self::Bool(left != right)),
-            (Self::Bool(left), Self::Bool(right)) => Ok(Self::Bool(left != right)),
-            _ => Err(format!(
-                "cannot compare inequality between {} and {}",
+    pub fn euclid_mod(&self, other: &Self) -> Result<Self, String> {
+        if other.is_zero() && self.to_f64().is_some() {
+            return Err("modulo by zero".to_string());
+        }
+
+        if let Some(result) = self.int_op(other, i64::checked_rem_euclid, |left, right| {
+            Euclid::rem_euclid(&left, &right)
+        }) {
+            return Ok(result);
+        }
+
+        match self.float_op(other) {
+            Some((left, right)) => Ok(Self::Float(left.rem_euclid(right))),
+            None => Err(format!(
+                "cannot modulo {} and {}",
                 self.name(),
                 other.name()
             )),
         }
     }
 
-    pub fn less(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Bool(left < right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Bool(left < right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Bool((*left as f64) < *right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Bool(left < &(*right as f64))),
-            (Self::String(left), Self::String(right)) => Ok(Self::Bool(left < right)),
-            _ => Err(format!(
-                "cannot compare less than between {} and {}",
-                self.name(),
-                other.name()
-            )),
+    // Integers raised to a non-negative integer stay integers; anything else gives a float.
+    pub fn pow(&self, other: &Self) -> Result<Self, String> {
+        if let (Some(base), Self::Int(exponent)) = (self.to_bigint(), other) {
+            if *exponent >= 0 {
+                let exponent = u32::try_from(*exponent)
+                    .map_err(|_| format!("exponent too large: {}", exponent))?;
+
+                if let Self::Int(base) = self {
+                    if let Some(result) = base.checked_pow(exponent) {
+                        return Ok(Self::Int(result));
+                    }
+                }
+
+                return Ok(Self::from_bigint(base.pow(exponent)));
+            }
+        }
+
+        match (self.to_f64(), other.to_f64()) {
+            (Some(left), Some(right)) => Ok(Self::Float(left.powf(right))),
+            _ => Err(format!("cannot raise {} to {}", self.name(), other.name())),
         }
     }
 
-    pub fn greater(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Bool(left > right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Bool(left > right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Bool(*left as f64 > *right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Bool(left > &(*right as f64))),
-            (Self::String(left), Self::String(right)) => Ok(Self::Bool(left > right)),
-            _ => Err(format!(
-                "cannot compare greater than between {} and {}",
-                self.name(),
-                other.name()
-            )),
+    pub fn bit_and(&self, other: &Self) -> Result<Self, String> {
+        if let (Self::Bool(left), Self::Bool(right)) = (self, other) {
+            return Ok(Self::Bool(left & right));
         }
+
+        self.int_op(
+            other,
+            |left, right| Some(left & right),
+            |left, right| left & right,
+        )
+        .ok_or_else(|| format!("cannot apply & to {} and {}", self.name(), other.name()))
     }
 
-    pub fn less_equals(&self, other: &Self) -> Result<Self, String> {
+    pub fn bit_or(&self, other: &Self) -> Result<Self, String> {
+        if let (Self::Bool(left), Self::Bool(right)) = (self, other) {
+            return Ok(Self::Bool(left | right));
+        }
+
+        self.int_op(
+            other,
+            |left, right| Some(left | right),
+            |left, right| left | right,
+        )
+        .ok_or_else(|| format!("cannot apply | to {} and {}", self.name(), other.name()))
+    }
+
+    pub fn bit_xor(&self, other: &Self) -> Result<Self, String> {
+        if let (Self::Bool(left), Self::Bool(right)) = (self, other) {
+            return Ok(Self::Bool(left ^ right));
+        }
+
+        self.int_op(
+            other,
+            |left, right| Some(left ^ right),
+            |left, right| left ^ right,
+        )
+        .ok_or_else(|| format!("cannot apply ^ to {} and {}", self.name(), other.name()))
+    }
+
+    fn shift_amount(&self, other: &Self, operator: &str) -> Result<(BigInt, usize), String> {
+        let (value, amount) = match (self.to_bigint(), other) {
+            (Some(value), Self::Int(amount)) => (value, *amount),
+            _ => {
+                return Err(format!(
+                    "cannot apply {} to {} and {}",
+                    operator,
+                    self.name(),
+                    other.name()
+                ))
+            }
+        };
+
+        match u32::try_from(amount) {
+            Ok(amount) => Ok((value, amount as usize)),
+            Err(_) if amount < 0 => Err(format!("negative shift amount: {}", amount)),
+            Err(_) => Err(format!("shift amount too large: {}", amount)),
+        }
+    }
+
+    pub fn shl(&self, other: &Self) -> Result<Self, String> {
+        let (value, amount) = self.shift_amount(other, "<<")?;
+
+        Ok(Self::from_bigint(value << amount))
+    }
+
+    // Shifts right, rounding towards negative infinity like an arithmetic shift.
+    pub fn shr(&self, other: &Self) -> Result<Self, String> {
+        let (value, amount) = self.shift_amount(other, ">>")?;
+
+        Ok(Self::from_bigint(value >> amount))
+    }
+
+    // Checks two values for equality. Arrays and tuples are equal if they have the same length
+    // and their elements are equal in turn.
+    pub fn equal(&self, other: &Self) -> Result<bool, String> {
         match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Bool(left <= right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Bool(left <= right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Bool(*left as f64 <= *right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Bool(left <= &(*right as f64))),
-            (Self::String(left), Self::String(right)) => Ok(Self::Bool(left <= right)),
+            (Self::Int(left), Self::Int(right)) => Ok(left == right),
+            (Self::Int(_) | Self::BigInt(_), Self::Int(_) | Self::BigInt(_)) => {
+                Ok(self.to_bigint() == other.to_bigint())
+            }
+            (Self::Float(_), _) | (_, Self::Float(_)) if self.float_op(other).is_some() => {
+                Ok(self.to_f64() == other.to_f64())
+            }
+            (Self::String(left), Self::String(right)) => Ok(left == right),
+            (Self::Bool(left), Self::Bool(right)) => Ok(left == right),
+            (Self::Array(left), Self::Array(right)) => {
+                Self::equal_all(&left.borrow(), &right.borrow())
+            }
+            (Self::Tuple(left), Self::Tuple(right)) => Self::equal_all(left, right),
+            (Self::Map(left), Self::Map(right)) => Ok(left == right),
+            (Self::Set(left), Self::Set(right)) => Ok(left == right),
+            // Anything can be checked against none, which is only equal to itself.
+            (Self::None, _) | (_, Self::None) => {
+                Ok(matches!((self, other), (Self::None, Self::None)))
+            }
             _ => Err(format!(
-                "cannot compare less than or equal to between {} and {}",
+                "cannot compare equality between {} and {}",
                 self.name(),
                 other.name()
             )),
         }
     }
 
+    fn equal_all(left: &[Self], right: &[Self]) -> Result<bool, String> {
+        if left.len() != right.len() {
+            return Ok(false);
+        }
+
+        for (left, right) in left.iter().zip(right) {
+            if !left.equal(right)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Orders two values. Arrays and tuples are ordered element by element, with a shorter one
+    // coming first if it matches the start of a longer one.
+    pub fn compare(&self, other: &Self) -> Result<Ordering, String> {
+        let ordering = match (self, other) {
+            (Self::Int(left), Self::Int(right)) => Some(left.cmp(right)),
+            (Self::Int(_) | Self::BigInt(_), Self::Int(_) | Self::BigInt(_)) => {
+                Some(self.to_bigint().cmp(&other.to_bigint()))
+            }
+            (Self::Float(_), _) | (_, Self::Float(_)) if self.float_op(other).is_some() => {
+                self.to_f64().partial_cmp(&other.to_f64())
+            }
+            (Self::String(left), Self::String(right)) => Some(left.cmp(right)),
+            (Self::Bool(left), Self::Bool(right)) => Some(left.cmp(right)),
+            (Self::None, Self::None) => Some(Ordering::Equal),
+            (Self::Array(left), Self::Array(right)) => {
+                return Self::compare_all(&left.borrow(), &right.borrow())
+            }
+            (Self::Tuple(left), Self::Tuple(right)) => return Self::compare_all(left, right),
+            _ => {
+                return Err(format!(
+                    "cannot compare {} and {}",
+                    self.name(),
+                    other.name()
+                ))
+            }
+        };
+
+        // Only NaN has no ordering.
+        ordering.ok_or_else(|| format!("cannot compare {} and {}", self, other))
+    }
+
+    fn compare_all(left: &[Self], right: &[Self]) -> Result<Ordering, String> {
+        for (left, right) in left.iter().zip(right) {
+            match left.compare(right)? {
+                Ordering::Equal => {}
+                ordering => return Ok(ordering),
+            }
+        }
+
+        Ok(left.len().cmp(&right.len()))
+    }
+
+    pub fn equals(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::Bool(self.equal(other)?))
+    }
+
+    pub fn not_equals(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::Bool(!self.equal(other)?))
+    }
+
+    pub fn less(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::Bool(self.compare(other)?.is_lt()))
+    }
+
+    pub fn greater(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::Bool(self.compare(other)?.is_gt()))
+    }
+
+    pub fn less_equals(&self, other: &Self) -> Result<Self, String> {
+        Ok(Self::Bool(self.compare(other)?.is_le()))
+    }
+
     pub fn greater_equals(&self, other: &Self) -> Result<Self, String> {
-        match (self, other) {
-            (Self::Int(left), Self::Int(right)) => Ok(Self::Bool(left >= right)),
-            (Self::Float(left), Self::Float(right)) => Ok(Self::Bool(left >= right)),
-            (Self::Int(left), Self::Float(right)) => Ok(Self::Bool(*left as f64 >= *right)),
-            (Self::Float(left), Self::Int(right)) => Ok(Self::Bool(left >= &(*right as f64))),
-            (Self::String(left), Self::String(right)) => Ok(Self::Bool(left >= right)),
-            _ => Err(format!(
-                "cannot compare greater than or equal to between {} and {}",
-                self.name(),
-                other.name()
-            )),
+        Ok(Self::Bool(self.compare(other)?.is_ge()))
+    }
+
+    // Resolves a possibly negative index against a sequence of length `len`.
+    fn offset(index: &Self, len: usize) -> Result<usize, String> {
+        let index = match index {
+            Self::Int(index) => *index,
+            Self::BigInt(_) => {
+                return Err(format!("index out of bounds: {} (length {})", index, len))
+            }
+            _ => return Err(format!("cannot index with {}", index.name())),
+        };
+
+        let offset = if index < 0 { index + len as i64 } else { index };
+
+        if offset < 0 || offset >= len as i64 {
+            return Err(format!("index out of bounds: {} (length {})", index, len));
+        }
+
+        Ok(offset as usize)
+    }
+
+    pub fn index(&self, index: &Self) -> Result<Self, String> {
+        match self {
+            Self::Array(array) => {
+                let array = array.borrow();
+                let offset = Self::offset(index, array.len())?;
+
+                Ok(array[offset].clone())
+            }
+            Self::Tuple(tuple) => Ok(tuple[Self::offset(index, tuple.len())?].clone()),
+            Self::String(string) => {
+                let chars = string.chars().collect::<Vec<char>>();
+                let offset = Self::offset(index, chars.len())?;
+
+                Ok(Self::String(chars[offset].to_string()))
+            }
+            Self::Map(map) => match map.borrow().get(&index.to_key()?) {
+                Some(value) => Ok(value.clone()),
+                None => Err(format!("key not found: {}", index)),
+            },
+            _ => Err(format!("cannot index {}", self.name())),
+        }
+    }
+
+    pub fn set_index(&self, index: &Self, value: Self) -> Result<(), String> {
+        match self {
+            Self::Array(array) => {
+                let offset = Self::offset(index, array.borrow().len())?;
+                array.borrow_mut()[offset] = value;
+
+                Ok(())
+            }
+            Self::Map(map) => {
+                map.borrow_mut().insert(index.to_key()?, value);
+
+                Ok(())
+            }
+            _ => Err(format!("cannot assign to index of {}", self.name())),
+        }
+    }
+
+    // Copies out the values of a collection: the elements of an array or set, the characters
+    // of a string, or the keys of a map.
+    pub fn items(&self) -> Result<Vec<Self>, String> {
+        match self {
+            Self::Array(array) => Ok(array.borrow().clone()),
+            Self::Tuple(tuple) => Ok(tuple.to_vec()),
+            Self::String(string) => Ok(string
+                .chars()
+                .map(|c| Self::String(c.to_string()))
+                .collect()),
+            Self::Map(map) => map.borrow().keys().map(Self::to_key).collect(),
+            Self::Set(set) => set.borrow().iter().map(Self::to_key).collect(),
+            _ => Err(format!("cannot iterate over {}", self.name())),
+        }
+    }
+
+    // Checks that a value can be used as a map key or set element. Arrays are copied, even
+    // inside tuples, so changing an array after using it as a key doesn't change the key.
+    // Stored keys are copied the same way when they're handed back out.
+    pub fn to_key(&self) -> Result<Self, String> {
+        match self {
+            Self::None | Self::Bool(_) | Self::Int(_) | Self::BigInt(_) | Self::String(_) => {
+                Ok(self.clone())
+            }
+            Self::Array(array) => {
+                let mut key = Vec::new();
+
+                for value in array.borrow().iter() {
+                    key.push(value.to_key()?);
+                }
+
+                Ok(Self::Array(Rc::new(RefCell::new(key))))
+            }
+            Self::Tuple(tuple) => {
+                let mut key = Vec::new();
+
+                for value in tuple.iter() {
+                    key.push(value.to_key()?);
+                }
+
+                Ok(Self::Tuple(Rc::new(key)))
+            }
+            _ => Err(format!("{} cannot be used as a key", self.name())),
         }
     }
 
     pub fn name(&self) -> String {
         match self {
+            Self::None => "none",
             Self::Bool(_) => "bool",
             Self::Int(_) => "int",
+            Self::BigInt(_) => "bigint",
             Self::Float(_) => "float",
             Self::String(_) => "string",
             Self::Identifier(_) => "identifier",
             Self::Array(_) => "array",
+            Self::Tuple(_) => "tuple",
+            Self::Map(_) => "map",
+            Self::Set(_) => "set",
         }
         .to_string()
     }
@@ -1,23 +1,100 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
     errors::Error,
-    frontend::parser::{BinOp, CallTarget, Compare, Statement, StatementContext, Value},
+    frontend::parser::{BinOp, CallTarget, Compare, Expr, Statement, StatementContext, Value},
 };
 
 use super::{debugger, VMValue};
 
 pub type VMFunc = fn(&mut VM, Vec<Option<String>>, Vec<VMValue>) -> Result<Option<VMValue>, String>;
 
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub index: usize,
+    pub end: usize,
+    pub params: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub return_index: usize,
+    pub locals: HashMap<String, VMValue>,
+    pub globals: HashSet<String>,
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LoopItems {
+    Range { next: i64, end: i64, step: i64 },
+    Values(Vec<VMValue>, usize),
+}
+
+// An active `@iter` loop. `start` and `end` are the indices of the statement that began it
+// and of its matching `@iter:end`, and `depth` is the number of frames it was started in.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub var: String,
+    pub items: LoopItems,
+    pub start: usize,
+    pub end: usize,
+    pub depth: usize,
+}
+
+impl Loop {
+    pub fn next(&mut self) -> Option<VMValue> {
+        match &mut self.items {
+            LoopItems::Range { next, end, step } => {
+                if (*step > 0 && *next >= *end) || (*step < 0 && *next <= *end) {
+                    return None;
+                }
+
+                let value = *next;
+                // Stepping past the largest int can only happen past the end, so stop there.
+                *next = next.checked_add(*step).unwrap_or(*end);
+
+                Some(VMValue::Int(value))
+            }
+            LoopItems::Values(values, index) => {
+                let value = values.get(*index).cloned();
+                *index += 1;
+
+                value
+            }
+        }
+    }
+}
+
+// The labels of a `match`, resolved to statement indices the first time it runs.
+#[derive(Debug)]
+pub struct JumpTable {
+    pub arms: HashMap<VMValue, usize>,
+    pub default: Option<usize>,
+}
+
 pub struct VM {
     pub statements: Vec<Statement>,
     pub funcs: HashMap<String, VMFunc>,
+    pub functions: HashMap<String, Function>,
     pub gotos: HashMap<String, usize>,
     pub variables: HashMap<String, VMValue>,
     pub index: usize,
-    pub call_stack: Vec<usize>,
+    pub frames: Vec<Frame>,
+    pub loops: Vec<Loop>,
+    // The index of the `@iter:end` for each `@iter` loop start, filled in as loops are reached.
+    pub loop_ends: HashMap<usize, usize>,
+    // Keyed by the index of the `match` statement.
+    pub jump_tables: HashMap<usize, Rc<JumpTable>>,
     pub stack: Vec<VMValue>,
     pub breakpoint: bool,
+    pub ret_value: Option<VMValue>,
+    pub error: Option<Error>,
+    // The script path followed by the arguments after it, for @std:getargs.
+    pub args: Vec<String>,
 }
 
 impl VM {
@@ -25,12 +102,19 @@ impl VM {
         Self {
             statements,
             funcs: HashMap::new(),
+            functions: HashMap::new(),
             gotos: HashMap::new(),
             variables: HashMap::new(),
             index: 0,
-            call_stack: Vec::new(),
+            frames: Vec::new(),
+            loops: Vec::new(),
+            loop_ends: HashMap::new(),
+            jump_tables: HashMap::new(),
             stack: Vec::new(),
             breakpoint: false,
+            ret_value: None,
+            error: None,
+            args: Vec::new(),
         }
     }
 
@@ -45,18 +129,219 @@ impl VM {
         idts: Vec<Option<String>>,
         args: Vec<VMValue>,
     ) -> Result<Option<VMValue>, String> {
-        let func = self.funcs.get(&format!("{}:{}", module, name));
+        let key = format!("{}:{}", module, name);
+        let func = self.funcs.get(&key);
 
         if let Some(func) = func {
             match func(self, idts, args) {
                 Ok(value) => return Ok(value),
                 Err(e) => return Err(e),
             }
-        } else {
-            return Err(format!("function not found: {}:{}", module, name));
+        }
+
+        if let Some(function) = self.functions.get(&key).cloned() {
+            return self.call_function(key, function, args);
+        }
+
+        Err(format!("function not found: {}:{}", module, name))
+    }
+
+    // Runs a user-defined function to completion. Used when the call comes
+    // from Rust (e.g. @std:map) rather than from a statement.
+    fn call_function(
+        &mut self,
+        name: String,
+        function: Function,
+        args: Vec<VMValue>,
+    ) -> Result<Option<VMValue>, String> {
+        let index = self.index;
+        let depth = self.frames.len();
+
+        self.enter_function(name, function, args, None)?;
+        self.index += 1;
+
+        while self.frames.len() > depth {
+            if let Err(e) = self.step() {
+                let message = e.message.clone();
+                self.error = Some(e);
+                return Err(message);
+            }
+        }
+
+        self.index = index;
+
+        Ok(self.ret_value.take())
+    }
+
+    fn enter_function(
+        &mut self,
+        name: String,
+        function: Function,
+        args: Vec<VMValue>,
+        target: Option<String>,
+    ) -> Result<(), String> {
+        if args.len() != function.params.len() {
+            return Err(format!(
+                "@{} expected {} arguments, got {}",
+                name,
+                function.params.len(),
+                args.len()
+            ));
+        }
+
+        let locals = function.params.into_iter().zip(args).collect();
+
+        self.frames.push(Frame {
+            return_index: self.index,
+            locals,
+            globals: HashSet::new(),
+            target,
+        });
+        self.index = function.index;
+
+        Ok(())
+    }
+
+    // Variables are looked up in the current frame first, then in the globals.
+    // Names declared with `global` always refer to the globals.
+    pub fn get_var(&self, identifier: &str) -> Option<&VMValue> {
+        if let Some(frame) = self.frames.last() {
+            if !frame.globals.contains(identifier) {
+                if let Some(value) = frame.locals.get(identifier) {
+                    return Some(value);
+                }
+            }
+        }
+
+        self.variables.get(identifier)
+    }
+
+    pub fn set_var(&mut self, identifier: String, value: VMValue) {
+        if let Some(frame) = self.frames.last_mut() {
+            if !frame.globals.contains(&identifier) {
+                frame.locals.insert(identifier, value);
+                return;
+            }
+        }
+
+        self.variables.insert(identifier, value);
+    }
+
+    pub fn remove_var(&mut self, identifier: &str) -> Option<VMValue> {
+        if let Some(frame) = self.frames.last_mut() {
+            if !frame.globals.contains(identifier) {
+                if let Some(value) = frame.locals.remove(identifier) {
+                    return Some(value);
+                }
+            }
+        }
+
+        self.variables.remove(identifier)
+    }
+
+    pub fn resolve(&self, value: Value) -> Result<VMValue, String> {
+        match value {
+            Value::Identifier(identifier) => match self.get_var(&identifier) {
+                Some(value) => Ok(value.clone()),
+                None => Err(format!("variable not found: {}", identifier)),
+            },
+            _ => Ok(VMValue::from(value)),
+        }
+    }
+
+    pub fn eval(&self, expr: Expr) -> Result<VMValue, String> {
+        match expr {
+            Expr::Value(value) => self.resolve(value),
+            Expr::Array(exprs) => {
+                let mut array = Vec::new();
+
+                for expr in exprs {
+                    array.push(self.eval(expr)?);
+                }
+
+                Ok(VMValue::Array(Rc::new(RefCell::new(array))))
+            }
+            Expr::Tuple(exprs) => {
+                let mut tuple = Vec::new();
+
+                for expr in exprs {
+                    tuple.push(self.eval(expr)?);
+                }
+
+                Ok(VMValue::Tuple(Rc::new(tuple)))
+            }
+            Expr::Index(value, index) => {
+                let value = self.eval(*value)?;
+                let index = self.eval(*index)?;
+
+                value.index(&index)
+            }
+            Expr::BinOp(binop) => match *binop {
+                BinOp::Add(lhs, rhs) => self.eval(lhs)?.add(&self.eval(rhs)?),
+                BinOp::Sub(lhs, rhs) => self.eval(lhs)?.sub(&self.eval(rhs)?),
+                BinOp::Mul(lhs, rhs) => self.eval(lhs)?.mul(&self.eval(rhs)?),
+                BinOp::Div(lhs, rhs) => self.eval(lhs)?.div(&self.eval(rhs)?),
+                BinOp::Mod(lhs, rhs) => self.eval(lhs)?.mod_(&self.eval(rhs)?),
+                BinOp::EuclidDiv(lhs, rhs) => self.eval(lhs)?.euclid_div(&self.eval(rhs)?),
+                BinOp::EuclidMod(lhs, rhs) => self.eval(lhs)?.euclid_mod(&self.eval(rhs)?),
+                BinOp::Pow(lhs, rhs) => self.eval(lhs)?.pow(&self.eval(rhs)?),
+                BinOp::BitAnd(lhs, rhs) => self.eval(lhs)?.bit_and(&self.eval(rhs)?),
+                BinOp::BitOr(lhs, rhs) => self.eval(lhs)?.bit_or(&self.eval(rhs)?),
+                BinOp::BitXor(lhs, rhs) => self.eval(lhs)?.bit_xor(&self.eval(rhs)?),
+                BinOp::Shl(lhs, rhs) => self.eval(lhs)?.shl(&self.eval(rhs)?),
+                BinOp::Shr(lhs, rhs) => self.eval(lhs)?.shr(&self.eval(rhs)?),
+            },
+            Expr::Compare(compare) => match *compare {
+                Compare::Equals(lhs, rhs) => self.eval(lhs)?.equals(&self.eval(rhs)?),
+                Compare::NotEquals(lhs, rhs) => self.eval(lhs)?.not_equals(&self.eval(rhs)?),
+                Compare::LessThan(lhs, rhs) => self.eval(lhs)?.less(&self.eval(rhs)?),
+                Compare::GreaterThan(lhs, rhs) => self.eval(lhs)?.greater(&self.eval(rhs)?),
+                Compare::LessThanEquals(lhs, rhs) => self.eval(lhs)?.less_equals(&self.eval(rhs)?),
+                Compare::GreaterThanEquals(lhs, rhs) => {
+                    self.eval(lhs)?.greater_equals(&self.eval(rhs)?)
+                }
+            },
+            // `and` and `or` only evaluate their right side when it decides the result.
+            Expr::And(lhs, rhs) => match self.eval_bool(*lhs, "and")? {
+                true => Ok(VMValue::Bool(self.eval_bool(*rhs, "and")?)),
+                false => Ok(VMValue::Bool(false)),
+            },
+            Expr::Or(lhs, rhs) => match self.eval_bool(*lhs, "or")? {
+                true => Ok(VMValue::Bool(true)),
+                false => Ok(VMValue::Bool(self.eval_bool(*rhs, "or")?)),
+            },
+            Expr::Not(expr) => Ok(VMValue::Bool(!self.eval_bool(*expr, "not")?)),
+            Expr::Neg(expr) => self.eval(*expr)?.neg(),
+        }
+    }
+
+    fn eval_bool(&self, expr: Expr, operator: &str) -> Result<bool, String> {
+        match self.eval(expr)? {
+            VMValue::Bool(value) => Ok(value),
+            value => Err(format!("cannot apply {} to {}", operator, value.name())),
         }
     }
 
+    fn resolve_args(
+        &self,
+        exprs: Vec<Expr>,
+    ) -> Result<(Vec<Option<String>>, Vec<VMValue>), String> {
+        let mut args = Vec::new();
+        let mut idts = Vec::new();
+
+        for expr in exprs {
+            if let Expr::Value(Value::Identifier(identifier)) = &expr {
+                idts.push(Some(identifier.clone()));
+            } else {
+                idts.push(None);
+            }
+
+            args.push(self.eval(expr)?);
+        }
+
+        Ok((idts, args))
+    }
+
     pub fn run(&mut self) -> Result<(), Error> {
         self.register(
             "vm".to_string(),
@@ -67,9 +352,44 @@ impl VM {
             },
         );
 
+        let mut current: Option<(String, Function)> = None;
+
         for (i, statement) in self.statements.iter().enumerate() {
-            if let StatementContext::GotoDef(identifier) = statement.context.clone() {
-                self.gotos.insert(identifier.clone(), i);
+            match statement.context.clone() {
+                StatementContext::GotoDef(identifier, _) => {
+                    if let Some(previous) = self.gotos.get(&identifier) {
+                        let previous = &self.statements[*previous];
+
+                        return Err(statement.error(format!(
+                            "label already defined at {}:{}: {}",
+                            previous.file, previous.line, identifier
+                        )));
+                    }
+
+                    self.gotos.insert(identifier.clone(), i);
+                }
+                StatementContext::FuncDef(target, params) => {
+                    current = Some((
+                        format!("{}:{}", target.module, target.function),
+                        Function {
+                            index: i,
+                            end: i,
+                            params,
+                        },
+                    ));
+                }
+                StatementContext::FuncEnd => {
+                    if let Some((name, mut function)) = current.take() {
+                        if self.funcs.contains_key(&name) || self.functions.contains_key(&name) {
+                            return Err(self.statements[function.index]
+                                .error(format!("function already defined: @{}", name)));
+                        }
+
+                        function.end = i;
+                        self.functions.insert(name, function);
+                    }
+                }
+                _ => {}
             }
         }
 
@@ -84,16 +404,18 @@ impl VM {
         let statement = self.statements[self.index].clone();
 
         let res = match statement.clone().context {
-            StatementContext::AssignLiteral(identifier, value) => {
-                self.op_assign_literal(identifier, value)
-            }
-            StatementContext::AssignBinOp(identifier, binop) => {
-                self.op_assign_binop(identifier, binop)
-            }
+            StatementContext::AssignExpr(identifier, expr) => self.op_assign_expr(identifier, expr),
             StatementContext::AssignCall(identifier, call_target, args) => {
                 self.op_assign_call(identifier, call_target, args)
             }
-            StatementContext::GotoDef(_) => Ok(()),
+            StatementContext::AssignIndex(identifier, indices, value) => {
+                self.op_assign_index(identifier, indices, value)
+            }
+            StatementContext::AssignCallLabel(identifier, label, args) => {
+                self.op_call_label(label, args, Some(identifier))
+            }
+            StatementContext::Unpack(targets, expr) => self.op_unpack(targets, expr),
+            StatementContext::GotoDef(_, _) => Ok(()),
             StatementContext::Goto(identifier) => {
                 if let Some(index) = self.gotos.get(&identifier) {
                     self.index = *index;
@@ -104,13 +426,27 @@ impl VM {
                 Ok(())
             }
             StatementContext::GotoIf(identifier, compare) => self.op_goto_if(identifier, compare),
+            StatementContext::Match(value, arms, default) => self.op_match(value, arms, default),
             StatementContext::Call(call_target, args) => self.op_call(call_target, args),
-            StatementContext::CallLabel(label) => self.op_call_label(label),
-            StatementContext::Ret => self.op_ret(),
+            StatementContext::CallLabel(label, args) => self.op_call_label(label, args, None),
+            StatementContext::Global(identifiers) => self.op_global(identifiers),
+            StatementContext::FuncDef(target, _) => {
+                // Definitions are skipped over when reached by normal control flow.
+                let name = format!("{}:{}", target.module, target.function);
+                self.index = self.functions[&name].end;
+
+                Ok(())
+            }
+            StatementContext::FuncEnd => self.op_ret(None),
+            StatementContext::Ret(value) => self.op_ret(value),
             StatementContext::EOS => Err("unexpected end of statement".to_string()),
         };
 
         if let Err(e) = res {
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+
             return Err(statement.error(e));
         }
 
@@ -125,90 +461,91 @@ impl VM {
         Ok(())
     }
 
-    fn op_assign_literal(&mut self, identifier: String, value: Value) -> Result<(), String> {
-        match value {
-            Value::Identifier(id) => {
-                if let Some(value) = self.variables.get(&id) {
-                    self.variables.insert(identifier, value.clone());
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
-                }
-            }
-            _ => {
-                self.variables.insert(identifier, VMValue::from(value));
-            },
-        }
+    fn op_assign_expr(&mut self, identifier: String, expr: Expr) -> Result<(), String> {
+        let value = self.eval(expr)?;
+
+        self.set_var(identifier, value);
 
         Ok(())
     }
 
-    fn op_assign_binop(&mut self, identifier: String, binop: BinOp) -> Result<(), String> {
-        let (raw_lhs, raw_rhs) = match binop.clone() {
-            BinOp::Add(lhs, rhs) => (lhs, rhs),
-            BinOp::Sub(lhs, rhs) => (lhs, rhs),
-            BinOp::Mul(lhs, rhs) => (lhs, rhs),
-            BinOp::Div(lhs, rhs) => (lhs, rhs),
-            BinOp::Mod(lhs, rhs) => (lhs, rhs),
-        };
-
-        let lhs = match raw_lhs {
-            Value::Identifier(identifier) => {
-                if let Some(value) = self.variables.get(&identifier) {
-                    value.clone()
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
-                }
+    // Native functions return several values as a tuple, so tuples and arrays can both be
+    // unpacked.
+    fn op_unpack(&mut self, targets: Vec<Option<String>>, expr: Expr) -> Result<(), String> {
+        // `a, b = @mod:fn` unpacks from a hidden variable holding the call's result, which
+        // isn't needed afterwards.
+        let value = match &expr {
+            Expr::Value(Value::Identifier(name)) if name.starts_with("@internal:") => {
+                self.remove_var(name)
             }
-            _ => VMValue::from(raw_lhs),
+            _ => None,
         };
 
-        let rhs = match raw_rhs {
-            Value::Identifier(identifier) => {
-                if let Some(value) = self.variables.get(&identifier) {
-                    value.clone()
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
-                }
-            }
-            _ => VMValue::from(raw_rhs),
+        let value = match value {
+            Some(value) => value,
+            None => self.eval(expr)?,
         };
 
-        let value = match binop {
-            BinOp::Add(_, _) => lhs.add(&rhs)?,
-            BinOp::Sub(_, _) => lhs.sub(&rhs)?,
-            BinOp::Mul(_, _) => lhs.mul(&rhs)?,
-            BinOp::Div(_, _) => lhs.div(&rhs)?,
-            BinOp::Mod(_, _) => lhs.mod_(&rhs)?,
+        let values = match value {
+            VMValue::Array(array) => array.borrow().clone(),
+            VMValue::Tuple(tuple) => tuple.as_ref().clone(),
+            value => return Err(format!("cannot unpack {}", value.name())),
         };
 
-        self.variables.insert(identifier, value);
+        if values.len() != targets.len() {
+            return Err(format!(
+                "expected {} values to unpack, got {}",
+                targets.len(),
+                values.len()
+            ));
+        }
+
+        for (target, value) in targets.into_iter().zip(values) {
+            if let Some(target) = target {
+                self.set_var(target, value);
+            }
+        }
 
         Ok(())
     }
 
+    fn op_assign_index(
+        &mut self,
+        identifier: String,
+        mut indices: Vec<Expr>,
+        value: Expr,
+    ) -> Result<(), String> {
+        let value = self.eval(value)?;
+        let last = self.eval(indices.pop().unwrap())?;
+
+        let mut target = match self.get_var(&identifier) {
+            Some(target) => target.clone(),
+            None => return Err(format!("variable not found: {}", identifier)),
+        };
+
+        // Arrays are shared, so walking down to the innermost one lets us modify it in place.
+        for index in indices {
+            let index = self.eval(index)?;
+            target = target.index(&index)?;
+        }
+
+        target.set_index(&last, value)
+    }
+
     fn op_assign_call(
         &mut self,
         identifier: String,
         call_target: CallTarget,
-        args: Vec<Value>,
+        args: Vec<Expr>,
     ) -> Result<(), String> {
-        let mut vmargs = Vec::<VMValue>::new();
-        let mut idts = Vec::new();
+        let (idts, vmargs) = self.resolve_args(args)?;
 
-        for value in args {
-            if let Value::Identifier(identifier) = value.clone() {
-                if let Some(value) = self.variables.get(&identifier) {
-                    vmargs.push(value.clone());
-                    idts.push(Some(identifier));
-                    continue;
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
-                }
-            } else {
-                idts.push(None);
-            }
+        let name = format!("{}:{}", call_target.module, call_target.function);
 
-            vmargs.push(VMValue::from(value));
+        if !self.funcs.contains_key(&name) {
+            if let Some(function) = self.functions.get(&name).cloned() {
+                return self.enter_function(name, function, vmargs, Some(identifier));
+            }
         }
 
         let value = self.call(
@@ -218,111 +555,171 @@ impl VM {
             vmargs,
         )?;
 
-        if let Some(value) = value {
-            self.variables.insert(identifier, value);
-        } else {
-            return Err(format!(
-                "function did not return a value: @{}:{}",
-                call_target.module, call_target.function
-            ));
-        }
+        self.set_var(identifier, value.unwrap_or(VMValue::None));
 
         Ok(())
     }
 
-    fn op_goto_if(&mut self, identifier: String, compare: Compare) -> Result<(), String> {
-        let (raw_lhs, raw_rhs) = match compare.clone() {
-            Compare::Equals(lhs, rhs) => (lhs, rhs),
-            Compare::NotEquals(lhs, rhs) => (lhs, rhs),
-            Compare::LessThanEquals(lhs, rhs) => (lhs, rhs),
-            Compare::GreaterThanEquals(lhs, rhs) => (lhs, rhs),
-            Compare::LessThan(lhs, rhs) => (lhs, rhs),
-            Compare::GreaterThan(lhs, rhs) => (lhs, rhs),
+    fn op_goto_if(&mut self, identifier: String, condition: Expr) -> Result<(), String> {
+        let jump = match self.eval(condition)? {
+            VMValue::Bool(jump) => jump,
+            value => return Err(format!("condition must be a bool, got {}", value.name())),
         };
 
-        let lhs = match raw_lhs {
-            Value::Identifier(identifier) => {
-                if let Some(value) = self.variables.get(&identifier) {
-                    value.clone()
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
-                }
+        if jump {
+            if let Some(index) = self.gotos.get(&identifier) {
+                self.index = *index;
+            } else {
+                return Err(format!("goto not found: {}", identifier));
             }
-            _ => VMValue::from(raw_lhs),
-        };
+        }
 
-        let rhs = match raw_rhs {
-            Value::Identifier(identifier) => {
-                if let Some(value) = self.variables.get(&identifier) {
-                    value.clone()
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
-                }
-            }
-            _ => VMValue::from(raw_rhs),
-        };
+        Ok(())
+    }
 
-        let value = match compare {
-            Compare::Equals(_, _) => lhs.equals(&rhs)?,
-            Compare::NotEquals(_, _) => lhs.not_equals(&rhs)?,
-            Compare::LessThanEquals(_, _) => lhs.less_equals(&rhs)?,
-            Compare::GreaterThanEquals(_, _) => lhs.greater_equals(&rhs)?,
-            Compare::LessThan(_, _) => lhs.less(&rhs)?,
-            Compare::GreaterThan(_, _) => lhs.greater(&rhs)?,
-        };
+    fn op_call(&mut self, target: CallTarget, values: Vec<Expr>) -> Result<(), String> {
+        let (idts, args) = self.resolve_args(values)?;
 
-        if let VMValue::Bool(jump) = value {
-            if jump {
-                if let Some(index) = self.gotos.get(&identifier) {
-                    self.index = *index;
-                } else {
-                    return Err(format!("goto not found: {}", identifier));
-                }
+        let name = format!("{}:{}", target.module, target.function);
+
+        if !self.funcs.contains_key(&name) {
+            if let Some(function) = self.functions.get(&name).cloned() {
+                return self.enter_function(name, function, args, None);
             }
         }
 
+        self.call(target.module, target.function, idts, args)?;
+
         Ok(())
     }
 
-    fn op_call(&mut self, target: CallTarget, values: Vec<Value>) -> Result<(), String> {
-        let mut args = Vec::new();
-        let mut idts = Vec::new();
+    fn label_index(&self, label: &str) -> Result<usize, String> {
+        match self.gotos.get(label) {
+            Some(index) => Ok(*index),
+            None => Err(format!("goto not found: {}", label)),
+        }
+    }
 
-        for value in values {
-            if let Value::Identifier(identifier) = value.clone() {
-                if let Some(value) = self.variables.get(&identifier) {
-                    args.push(value.clone());
-                    idts.push(Some(identifier));
-                    continue;
-                } else {
-                    return Err(format!("variable not found: {}", identifier));
+    // Values are looked up by hash, so a `match` costs the same however many arms it has. Only
+    // values of the same type match, e.g. `1.0` doesn't match an arm for `1`.
+    fn op_match(
+        &mut self,
+        value: Expr,
+        arms: Vec<(Value, String)>,
+        default: Option<String>,
+    ) -> Result<(), String> {
+        let table = match self.jump_tables.get(&self.index) {
+            Some(table) => table.clone(),
+            None => {
+                let mut table = JumpTable {
+                    arms: HashMap::new(),
+                    default: None,
+                };
+
+                for (pattern, label) in arms {
+                    table
+                        .arms
+                        .insert(VMValue::from(pattern), self.label_index(&label)?);
                 }
-            } else {
-                idts.push(None);
+
+                if let Some(default) = default {
+                    table.default = Some(self.label_index(&default)?);
+                }
+
+                let table = Rc::new(table);
+                self.jump_tables.insert(self.index, table.clone());
+
+                table
             }
+        };
 
-            args.push(VMValue::from(value));
+        let value = self.eval(value)?;
+
+        if let Some(index) = table.arms.get(&value).or(table.default.as_ref()) {
+            self.index = *index;
         }
 
-        self.call(target.module, target.function, idts, args)?;
+        Ok(())
+    }
+
+    fn op_call_label(
+        &mut self,
+        label: String,
+        values: Vec<Expr>,
+        target: Option<String>,
+    ) -> Result<(), String> {
+        let index = match self.gotos.get(&label) {
+            Some(index) => *index,
+            None => return Err(format!("goto not found: {}", label)),
+        };
+
+        let params = match &self.statements[index].context {
+            StatementContext::GotoDef(_, params) => params.clone(),
+            _ => unreachable!(),
+        };
+
+        let (_, args) = self.resolve_args(values)?;
+
+        if args.len() != params.len() {
+            let call = &self.statements[self.index];
+            let message = format!(
+                "~{} expected {} arguments, got {} (called from {}:{})",
+                label,
+                params.len(),
+                args.len(),
+                call.file,
+                call.line
+            );
+
+            self.error = Some(self.statements[index].error(message.clone()));
+            return Err(message);
+        }
+
+        self.frames.push(Frame {
+            return_index: self.index,
+            locals: params.into_iter().zip(args).collect(),
+            globals: HashSet::new(),
+            target,
+        });
+        self.index = index;
 
         Ok(())
     }
 
-    fn op_call_label(&mut self, label: String) -> Result<(), String> {
-        self.call_stack.push(self.index);
-        self.index = *self.gotos.get(&label).unwrap();
+    fn op_global(&mut self, identifiers: Vec<String>) -> Result<(), String> {
+        if let Some(frame) = self.frames.last_mut() {
+            for identifier in identifiers {
+                frame.locals.remove(&identifier);
+                frame.globals.insert(identifier);
+            }
+        }
 
         Ok(())
     }
 
-    fn op_ret(&mut self) -> Result<(), String> {
-        if let Some(index) = self.call_stack.pop() {
-            self.index = index;
-        } else {
-            return Err("call stack is empty".to_string());
+    fn op_ret(&mut self, value: Option<Expr>) -> Result<(), String> {
+        let value = match value {
+            Some(value) => Some(self.eval(value)?),
+            None => None,
+        };
+
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return Err("call stack is empty".to_string()),
+        };
+
+        self.index = frame.return_index;
+
+        // Returning abandons any loops the call was still inside.
+        let depth = self.frames.len();
+        self.loops.retain(|l| l.depth <= depth);
+
+        if let Some(target) = frame.target {
+            self.set_var(target, value.clone().unwrap_or(VMValue::None));
         }
 
+        self.ret_value = value;
+
         Ok(())
     }
 }
@@ -1,160 +1,304 @@
-use crate::frontend::parser::{BinOp, Compare, Statement, StatementContext, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-fn vvm_emit_load_value(v: &Value) -> String {
+use crate::errors::{Error, ErrorLocation};
+use crate::frontend::parser::{
+    BinOp, CallTarget, Compare, Expr, Statement, StatementContext, Value,
+};
+use crate::vm::VMValue;
+
+type Operator = fn(&VMValue, &VMValue) -> Result<VMValue, String>;
+
+// VVM has no opcodes for these operators, so they're called as natives which use the
+// interpreter's arithmetic.
+const NATIVE_OPERATORS: [(&str, Operator); 8] = [
+    ("op:euclid_div", VMValue::euclid_div),
+    ("op:euclid_mod", VMValue::euclid_mod),
+    ("op:pow", VMValue::pow),
+    ("op:bit_and", VMValue::bit_and),
+    ("op:bit_or", VMValue::bit_or),
+    ("op:bit_xor", VMValue::bit_xor),
+    ("op:shl", VMValue::shl),
+    ("op:shr", VMValue::shr),
+];
+
+// Counter for the labels generated by short-circuiting `and`/`or`.
+static LOGIC_LABELS: AtomicUsize = AtomicUsize::new(0);
+
+fn vvm_emit_load_value(v: &Value) -> Result<String, String> {
     match v {
-        Value::Int(i) => format!("PUSHI {}", i),
-        Value::Float(f) => format!("PUSHF {}", f),
-        Value::Identifier(name) => format!("LOAD_IMM {}", name),
-        Value::Bool(b) => format!("PUSHB {}", b),
-        Value::String(s) => format!("PUSHS {}", s.replace("\n", "\\n")),
+        Value::Int(i) => Ok(format!("PUSHI {}", i)),
+        Value::Float(f) => Ok(format!("PUSHF {}", f)),
+        Value::Identifier(name) => Ok(format!("LOAD_IMM {}", name)),
+        Value::Bool(b) => Ok(format!("PUSHB {}", b)),
+        // `vvm::Value` has no none variant to push.
+        Value::None => Err("VVM does not support none".to_string()),
+        Value::String(s) => Ok(format!("PUSHS {}", s.replace("\n", "\\n"))),
     }
 }
 
-fn vvm_emit_binop(op: &BinOp) -> Vec<String> {
-    let mut lines = Vec::new();
-
-    match op {
-        BinOp::Add(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("ADD".into());
-            lines
+// `depth` is the number of array literals being built around this expression.
+fn vvm_emit_expr(expr: &Expr, depth: usize) -> Result<Vec<String>, String> {
+    match expr {
+        Expr::Value(value) => Ok(vec![vvm_emit_load_value(value)?]),
+        Expr::Array(values) => vvm_emit_array(values, depth),
+        // VVM has no tuple type, so tuples are built as arrays.
+        Expr::Tuple(values) => vvm_emit_array(values, depth),
+        Expr::Index(value, index) => {
+            let mut lines = vvm_emit_expr(value, depth)?;
+            lines.append(&mut vvm_emit_expr(index, depth)?);
+            lines.push("CALLNATIVE array:get".into());
+            Ok(lines)
         }
-        BinOp::Sub(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("SUB".into());
-            lines
+        Expr::BinOp(binop) => vvm_emit_binop(binop, depth),
+        Expr::Compare(compare) => vvm_emit_compare(compare, depth),
+        Expr::And(lhs, rhs) => {
+            let n = LOGIC_LABELS.fetch_add(1, Ordering::Relaxed);
+            let mut lines = vvm_emit_expr(lhs, depth)?;
+            lines.push(format!("JMPIF __aocl_and_rhs_{}", n));
+            lines.push("PUSHB false".into());
+            lines.push(format!("JMP __aocl_and_end_{}", n));
+            lines.push(format!("LABEL __aocl_and_rhs_{}", n));
+            lines.append(&mut vvm_emit_expr(rhs, depth)?);
+            lines.push(format!("LABEL __aocl_and_end_{}", n));
+            Ok(lines)
         }
-        BinOp::Mul(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("MUL".into());
-            lines
+        Expr::Or(lhs, rhs) => {
+            let n = LOGIC_LABELS.fetch_add(1, Ordering::Relaxed);
+            let mut lines = vvm_emit_expr(lhs, depth)?;
+            lines.push(format!("JMPIF __aocl_or_true_{}", n));
+            lines.append(&mut vvm_emit_expr(rhs, depth)?);
+            lines.push(format!("JMP __aocl_or_end_{}", n));
+            lines.push(format!("LABEL __aocl_or_true_{}", n));
+            lines.push("PUSHB true".into());
+            lines.push(format!("LABEL __aocl_or_end_{}", n));
+            Ok(lines)
         }
-        BinOp::Div(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("DIV".into());
-            lines
+        Expr::Not(expr) => {
+            let mut lines = vvm_emit_expr(expr, depth)?;
+            lines.push("PUSHB false".into());
+            lines.push("CMPEQ".into());
+            Ok(lines)
         }
-        BinOp::Mod(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("MOD".into());
-            lines
+        Expr::Neg(expr) => {
+            let mut lines = vec!["PUSHI 0".to_string()];
+            lines.append(&mut vvm_emit_expr(expr, depth)?);
+            lines.push("SUB".into());
+            Ok(lines)
         }
     }
 }
 
-fn vvm_emit_compare(cmp: &Compare) -> Vec<String> {
-    let mut lines = Vec::new();
+// Arrays are built in a temporary per nesting depth so inner literals don't clobber outer ones.
+fn vvm_emit_array(values: &Vec<Expr>, depth: usize) -> Result<Vec<String>, String> {
+    let temp = format!("__aocl_array_{}", depth);
+    let mut lines = vec![
+        "CALLNATIVE array:new".to_string(),
+        format!("STORE_IMM {}", temp),
+    ];
 
-    match cmp {
-        Compare::Equals(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("CMPEQ".into());
-            lines
-        }
-        Compare::NotEquals(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("CMPNE".into());
-            lines
-        }
-        Compare::LessThan(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("CMPLT".into());
-            lines
-        }
-        Compare::GreaterThan(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("CMPGT".into());
-            lines
-        }
-        Compare::LessThanEquals(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("CMPLE".into());
-            lines
-        }
-        Compare::GreaterThanEquals(lhs, rhs) => {
-            lines.push(vvm_emit_load_value(lhs));
-            lines.push(vvm_emit_load_value(rhs));
-            lines.push("CMPGE".into());
-            lines
-        }
+    for value in values {
+        lines.append(&mut vvm_emit_expr(value, depth + 1)?);
+        lines.push(format!("LOAD_IMM {}", temp));
+        lines.push("CALLNATIVE array:push".into());
+        lines.push(format!("STORE_IMM {}", temp));
     }
+
+    lines.push(format!("LOAD_IMM {}", temp));
+    Ok(lines)
 }
 
-pub fn vvm_emit(stmts: &Vec<Statement>) -> String {
-    let mut lines = Vec::new();
+fn vvm_emit_binop(op: &BinOp, depth: usize) -> Result<Vec<String>, String> {
+    let (lhs, rhs, opcode) = match op {
+        BinOp::Add(lhs, rhs) => (lhs, rhs, "ADD"),
+        BinOp::Sub(lhs, rhs) => (lhs, rhs, "SUB"),
+        BinOp::Mul(lhs, rhs) => (lhs, rhs, "MUL"),
+        BinOp::Div(lhs, rhs) => (lhs, rhs, "DIV"),
+        BinOp::Mod(lhs, rhs) => (lhs, rhs, "MOD"),
+        BinOp::EuclidDiv(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:euclid_div"),
+        BinOp::EuclidMod(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:euclid_mod"),
+        BinOp::Pow(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:pow"),
+        BinOp::BitAnd(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:bit_and"),
+        BinOp::BitOr(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:bit_or"),
+        BinOp::BitXor(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:bit_xor"),
+        BinOp::Shl(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:shl"),
+        BinOp::Shr(lhs, rhs) => (lhs, rhs, "CALLNATIVE op:shr"),
+    };
 
-    for stmt in stmts {
-        match &stmt.context {
-            StatementContext::AssignBinOp(name, op) => {
-                let mut ls = vvm_emit_binop(op);
-                lines.append(&mut ls);
-                lines.push(format!("STORE_IMM {}", name));
-            }
-            StatementContext::AssignLiteral(name, value) => {
-                lines.push(vvm_emit_load_value(value));
-                lines.push(format!("STORE_IMM {}", name));
-            }
-            StatementContext::AssignCall(name, target, args) => {
-                for arg in args {
-                    lines.push(vvm_emit_load_value(arg));
+    let mut lines = vvm_emit_expr(lhs, depth)?;
+    lines.append(&mut vvm_emit_expr(rhs, depth)?);
+    lines.push(opcode.into());
+    Ok(lines)
+}
+
+fn vvm_emit_compare(cmp: &Compare, depth: usize) -> Result<Vec<String>, String> {
+    let (lhs, rhs, opcode) = match cmp {
+        Compare::Equals(lhs, rhs) => (lhs, rhs, "CMPEQ"),
+        Compare::NotEquals(lhs, rhs) => (lhs, rhs, "CMPNE"),
+        Compare::LessThan(lhs, rhs) => (lhs, rhs, "CMPLT"),
+        Compare::GreaterThan(lhs, rhs) => (lhs, rhs, "CMPGT"),
+        Compare::LessThanEquals(lhs, rhs) => (lhs, rhs, "CMPLE"),
+        Compare::GreaterThanEquals(lhs, rhs) => (lhs, rhs, "CMPGE"),
+    };
+
+    let mut lines = vvm_emit_expr(lhs, depth)?;
+    lines.append(&mut vvm_emit_expr(rhs, depth)?);
+    lines.push(opcode.into());
+    Ok(lines)
+}
+
+fn vvm_error(stmt: &Statement, message: String) -> Error {
+    let mut error = stmt.error(message);
+    error.location = ErrorLocation::Compiler;
+    error
+}
+
+// The `iter` module keeps its loop state in the interpreter, which VVM doesn't have.
+fn vvm_check_target(target: &CallTarget) -> Result<(), String> {
+    if target.module == "iter" {
+        return Err(format!(
+            "VVM does not support @iter:{} (used by `for x in items` loops)",
+            target.function
+        ));
+    }
+
+    Ok(())
+}
+
+fn vvm_emit_args(args: &[Expr], lines: &mut Vec<String>) -> Result<(), String> {
+    for arg in args {
+        lines.append(&mut vvm_emit_expr(arg, 0)?);
+    }
+
+    Ok(())
+}
+
+fn vvm_emit_statement(stmt: &Statement, lines: &mut Vec<String>) -> Result<(), String> {
+    match &stmt.context {
+        StatementContext::AssignExpr(name, expr) => {
+            lines.append(&mut vvm_emit_expr(expr, 0)?);
+            lines.push(format!("STORE_IMM {}", name));
+        }
+        StatementContext::AssignCall(name, target, args) => {
+            vvm_check_target(target)?;
+            vvm_emit_args(args, lines)?;
+            lines.push(format!("CALLNATIVE {}:{}", target.module, target.function));
+            lines.push(format!("STORE_IMM {}", name));
+        }
+        // Too few values fail in `array:get`; VVM has no way to report too many.
+        StatementContext::Unpack(targets, expr) => {
+            lines.append(&mut vvm_emit_expr(expr, 0)?);
+            lines.push("STORE_IMM __aocl_unpack".into());
+
+            for (i, target) in targets.iter().enumerate() {
+                if let Some(target) = target {
+                    lines.push("LOAD_IMM __aocl_unpack".into());
+                    lines.push(format!("PUSHI {}", i));
+                    lines.push("CALLNATIVE array:get".into());
+                    lines.push(format!("STORE_IMM {}", target));
                 }
-                lines.push(format!("CALLNATIVE {}:{}", target.module, target.function));
-                lines.push(format!("STORE_IMM {}", name));
             }
-            StatementContext::Call(target, args) => {
-                if target.module == "array" && target.function == "push" {
-                    let name = match &args[0] {
-                        Value::Identifier(n) => n,
-                        _ => panic!("Expected identifier as first argument to array:push"),
-                    };
-                    for arg in &args[1..] {
-                        lines.push(vvm_emit_load_value(arg));
-                    }
-                    lines.push(format!("LOAD_IMM {}", name));
-                    lines.push(format!("CALLNATIVE {}:{}", target.module, target.function));
-                    lines.push(format!("STORE_IMM {}", name));
-                    continue;
-                }
-                for arg in args {
-                    lines.push(vvm_emit_load_value(arg));
-                }
+        }
+        StatementContext::Call(target, args) => {
+            vvm_check_target(target)?;
+
+            if target.module == "array" && target.function == "push" {
+                // VVM arrays are values, so the result has to be stored back into a variable.
+                let name = match &args[0] {
+                    Expr::Value(Value::Identifier(n)) => n,
+                    _ => return Err("VVM only supports @array:push on a variable".to_string()),
+                };
+                vvm_emit_args(&args[1..], lines)?;
+                lines.push(format!("LOAD_IMM {}", name));
                 lines.push(format!("CALLNATIVE {}:{}", target.module, target.function));
+                lines.push(format!("STORE_IMM {}", name));
+                return Ok(());
             }
-            StatementContext::Goto(name) => {
-                lines.push(format!("JMP {}", name));
-            }
-            StatementContext::GotoIf(name, cond) => {
-                let mut ls = vvm_emit_compare(cond);
-                lines.append(&mut ls);
-                lines.push(format!("JMPIF {}", name));
-            }
-            StatementContext::GotoDef(name) => {
-                lines.push(format!("LABEL {}", name));
+
+            vvm_emit_args(args, lines)?;
+            lines.push(format!("CALLNATIVE {}:{}", target.module, target.function));
+        }
+        StatementContext::Goto(name) => {
+            lines.push(format!("JMP {}", name));
+        }
+        StatementContext::GotoIf(name, cond) => {
+            lines.append(&mut vvm_emit_expr(cond, 0)?);
+            lines.push(format!("JMPIF {}", name));
+        }
+        // VVM has no jump tables, so each arm is compared in turn.
+        StatementContext::Match(value, arms, default) => {
+            lines.append(&mut vvm_emit_expr(value, 0)?);
+            lines.push("STORE_IMM __aocl_match".into());
+
+            for (pattern, label) in arms {
+                lines.push("LOAD_IMM __aocl_match".into());
+                lines.push(vvm_emit_load_value(pattern)?);
+                lines.push("CMPEQ".into());
+                lines.push(format!("JMPIF {}", label));
             }
-            StatementContext::Ret => {
-                lines.push(format!("RET"));
+
+            if let Some(default) = default {
+                lines.push(format!("JMP {}", default));
             }
-            _ => {
-                lines.push(format!("// Unhandled statement: {:?}", stmt));
+        }
+        StatementContext::GotoDef(name, _) => {
+            lines.push(format!("LABEL {}", name));
+        }
+        StatementContext::Ret(value) => {
+            if let Some(value) = value {
+                lines.append(&mut vvm_emit_expr(value, 0)?);
             }
+            lines.push("RET".to_string());
+        }
+        StatementContext::AssignIndex(_, _, _) => {
+            return Err("VVM does not support assigning to an index".to_string());
+        }
+        StatementContext::CallLabel(_, _) | StatementContext::AssignCallLabel(_, _, _) => {
+            return Err("VVM does not support `call`".to_string());
         }
+        StatementContext::Global(_) => {
+            return Err("VVM does not support `global`".to_string());
+        }
+        StatementContext::FuncDef(_, _) | StatementContext::FuncEnd => {
+            return Err("VVM does not support functions defined with `fn`".to_string());
+        }
+        StatementContext::EOS => {
+            return Err("unexpected end of statement".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn vvm_emit(stmts: &Vec<Statement>) -> Result<String, Error> {
+    let mut lines = Vec::new();
+
+    for stmt in stmts {
+        vvm_emit_statement(stmt, &mut lines).map_err(|e| vvm_error(stmt, e))?;
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn vvm_to_value(value: vvm::Value) -> Option<VMValue> {
+    match value {
+        vvm::Value::Int(i) => Some(VMValue::Int(i)),
+        vvm::Value::Float(f) => Some(VMValue::Float(f)),
+        vvm::Value::Bool(b) => Some(VMValue::Bool(b)),
+        _ => None,
     }
+}
 
-    lines.join("\n")
+fn vvm_from_value(value: VMValue) -> Option<vvm::Value> {
+    match value {
+        VMValue::Int(i) => Some(vvm::Value::Int(i)),
+        VMValue::Float(f) => Some(vvm::Value::Float(f)),
+        VMValue::Bool(b) => Some(vvm::Value::Bool(b)),
+        _ => None,
+    }
 }
 
-pub fn vvm_run(stmts: &Vec<Statement>) {
-    let vvm_code = vvm_emit(stmts);
+pub fn vvm_run(stmts: &Vec<Statement>) -> Result<(), Error> {
+    let vvm_code = vvm_emit(stmts)?;
     let program = vvm::Program::from_source(&vvm_code).unwrap();
     let mut vm = vvm::VM::new(program);
 
@@ -410,5 +554,34 @@ pub fn vvm_run(stmts: &Vec<Statement>) {
         }
     });
 
+    for (name, operator) in NATIVE_OPERATORS {
+        vm.register_native_handler(name, move |vm| {
+            let right = vvm_to_value(vm.pop());
+            let left = vvm_to_value(vm.pop());
+
+            let (left, right) = match (left, right) {
+                (Some(left), Some(right)) => (left, right),
+                _ => {
+                    eprintln!("Error: {} expects numbers or bools", name);
+                    std::process::exit(1);
+                }
+            };
+
+            match operator(&left, &right).map(vvm_from_value) {
+                Ok(Some(result)) => vm.push(result),
+                Ok(None) => {
+                    eprintln!("Error: {} result is too large for VVM", name);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        });
+    }
+
     vm.run();
+
+    Ok(())
 }